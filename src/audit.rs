@@ -0,0 +1,176 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use screeps::{HasPosition, Position, RoomVisual};
+use serde::Serialize;
+
+use crate::jobs::{JobClass, JobType};
+use crate::relogic::CostParts;
+
+/// Cheapest candidates kept per creep per tick.
+const TOP_K: usize = 3;
+
+/// Ticks of decisions kept in the trail.
+const HISTORY_TICKS: usize = 20;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Candidate {
+    pub job: JobType,
+    pub target: Position,
+    pub class: JobClass,
+    pub cost: CostParts,
+}
+
+/// What one creep was offered on one tick and what it ended up with.
+#[derive(Debug, Clone, Serialize)]
+pub struct Decision {
+    pub creep: String,
+    pub candidates: Vec<Candidate>,
+    pub chosen: Option<Candidate>,
+    /// held on to its job instead of being matched again
+    pub kept: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Tick {
+    tick: u32,
+    decisions: Vec<Decision>,
+}
+
+thread_local! {
+    static TRAIL: RefCell<VecDeque<Tick>> = RefCell::new(VecDeque::new());
+}
+
+/// Starts this tick's page of the trail, dropping the oldest.
+pub fn begin() {
+    let time = screeps::game::time();
+    TRAIL.with(|t| {
+        let mut trail = t.borrow_mut();
+        if trail.back().map(|p| p.tick) != Some(time) {
+            trail.push_back(Tick {
+                tick: time,
+                decisions: vec![],
+            });
+        }
+        while trail.len() > HISTORY_TICKS {
+            trail.pop_front();
+        }
+    });
+}
+
+fn with_decision(creep: &str, f: impl FnOnce(&mut Decision)) {
+    TRAIL.with(|t| {
+        let mut trail = t.borrow_mut();
+        let page = match trail.back_mut() {
+            Some(p) => p,
+            None => return,
+        };
+        let pos = match page.decisions.iter().position(|d| d.creep == creep) {
+            Some(pos) => pos,
+            None => {
+                page.decisions.push(Decision {
+                    creep: creep.to_string(),
+                    candidates: vec![],
+                    chosen: None,
+                    kept: false,
+                });
+                page.decisions.len() - 1
+            }
+        };
+        f(&mut page.decisions[pos]);
+    });
+}
+
+/// Adds the jobs `creep` was priced against in one solve; over every solve of
+/// the tick only the cheapest few are kept.
+pub fn consider(creep: &str, candidates: Vec<Candidate>) {
+    with_decision(creep, |d| {
+        d.candidates.extend(candidates);
+        d.candidates.sort_by_key(|c| c.cost.total());
+        d.candidates.truncate(TOP_K);
+    });
+}
+
+pub fn decide(creep: &str, chosen: Option<Candidate>, kept: bool) {
+    with_decision(creep, |d| {
+        d.chosen = chosen;
+        d.kept = kept;
+    });
+}
+
+fn describe(c: &Candidate) -> String {
+    format!(
+        "{:?} {:?} @ {} = {} (body {}, distance {}, store {}, priority {}, switch {})",
+        c.class,
+        c.job,
+        c.target,
+        c.cost.total(),
+        c.cost.body,
+        c.cost.distance,
+        c.cost.store,
+        c.cost.priority,
+        c.cost.switch
+    )
+}
+
+/// Every recorded decision for `creep`, oldest first; what `audit(name)` prints.
+pub fn report(creep: &str) -> String {
+    TRAIL.with(|t| {
+        let mut out = vec![];
+        for page in t.borrow().iter() {
+            for d in page.decisions.iter().filter(|d| d.creep == creep) {
+                let chosen = d
+                    .chosen
+                    .as_ref()
+                    .map(describe)
+                    .unwrap_or("idle".to_string());
+                out.push(format!(
+                    "[{}] {} {}",
+                    page.tick,
+                    if d.kept { "kept" } else { "chose" },
+                    chosen
+                ));
+                for c in d.candidates.iter() {
+                    out.push(format!("    {}", describe(c)));
+                }
+            }
+        }
+        if out.is_empty() {
+            format!("no decisions recorded for {}", creep)
+        } else {
+            out.join("\n")
+        }
+    })
+}
+
+/// Draws each creep's latest choice: a line to its target and the cost.
+pub fn draw() {
+    TRAIL.with(|t| {
+        let trail = t.borrow();
+        let page = match trail.back() {
+            Some(p) => p,
+            None => return,
+        };
+        for d in page.decisions.iter() {
+            let (creep, chosen) = match (screeps::game::creeps::get(&d.creep), &d.chosen) {
+                (Some(creep), Some(chosen)) => (creep, chosen),
+                _ => continue,
+            };
+            let pos = creep.pos();
+            let visual = RoomVisual::new(Some(pos.room_name()));
+            if chosen.target.room_name() == pos.room_name() {
+                visual.line(
+                    (pos.x() as f32, pos.y() as f32),
+                    (chosen.target.x() as f32, chosen.target.y() as f32),
+                    None,
+                );
+            }
+            visual.text(
+                pos.x() as f32,
+                pos.y() as f32 - 0.5,
+                format!("{:?} {}", chosen.job, chosen.cost.total()),
+                None,
+            );
+        }
+    });
+}
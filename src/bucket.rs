@@ -169,6 +169,46 @@ impl Context {
                 creep.move_to(&self.target);
             }
             JobType::Scout => todo!(),
+            JobType::Dismantle => {
+                let target = self
+                    .target
+                    .look_for(screeps::look::STRUCTURES)
+                    .into_iter()
+                    .find(|st| st.structure_type() != screeps::StructureType::Road);
+                if let Some(st) = target {
+                    match creep.dismantle(&st) {
+                        screeps::ReturnCode::NotInRange => {
+                            creep.move_to(&self.target);
+                        }
+                        screeps::ReturnCode::Ok => {}
+                        r => info!("{:?} couldn't dismantle: {:?}", creep.name(), r),
+                    }
+                }
+            }
+            JobType::Renew => {
+                if let Some(spawn) = self.target.find_closest_by_range(screeps::find::MY_SPAWNS) {
+                    match spawn.renew_creep(creep) {
+                        screeps::ReturnCode::NotInRange => {
+                            creep.move_to(&spawn);
+                        }
+                        screeps::ReturnCode::Ok | screeps::ReturnCode::Full => {}
+                        r => info!("{:?} couldn't be renewed: {:?}", creep.name(), r),
+                    }
+                }
+            }
+            JobType::Recycle => {
+                if let Some(spawn) = self.target.find_closest_by_range(screeps::find::MY_SPAWNS) {
+                    match spawn.recycle_creep(creep) {
+                        screeps::ReturnCode::NotInRange => {
+                            creep.move_to(&spawn);
+                        }
+                        screeps::ReturnCode::Ok => {
+                            info!("{:?} recycled", creep.name());
+                        }
+                        _ => {}
+                    }
+                }
+            }
             JobType::Reserve => todo!(),
             JobType::Attack => todo!(),
             JobType::AttackR => todo!(),
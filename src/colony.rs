@@ -0,0 +1,53 @@
+use screeps::{RoomName, RoomObjectProperties};
+
+use crate::{filters, meta};
+
+/// Unowned rooms this many rooms or fewer from a home room are its remotes.
+const REMOTE_RANGE: u32 = 1;
+
+/// Home rooms: every room we own a structure in.
+pub fn homes() -> Vec<RoomName> {
+    filters::get_my_rooms().iter().map(|r| r.name()).collect()
+}
+
+/// The colony `room` belongs to: itself if it's a home, otherwise the
+/// closest home within remote range.
+pub fn home_of(room: RoomName, homes: &[RoomName]) -> Option<RoomName> {
+    if homes.contains(&room) {
+        return Some(room);
+    }
+    homes
+        .iter()
+        .map(|&h| {
+            (
+                h,
+                screeps::game::map::get_room_linear_distance(h, room, false),
+            )
+        })
+        .filter(|&(_, d)| d <= REMOTE_RANGE)
+        .min_by_key(|&(_, d)| d)
+        .map(|(h, _)| h)
+}
+
+/// The colony a creep works for: its metadata's home when set, else wherever it is now.
+pub fn creep_home(creep: &screeps::Creep, homes: &[RoomName]) -> Option<RoomName> {
+    match meta::read(creep) {
+        Some(m) => Some(m.home),
+        None => creep.room().and_then(|r| home_of(r.name(), homes)),
+    }
+}
+
+/// The creeps among `creeps` working for `home`.
+pub fn members(home: RoomName, creeps: &[screeps::Creep]) -> Vec<screeps::Creep> {
+    let homes = homes();
+    creeps
+        .iter()
+        .filter(|c| creep_home(c, &homes) == Some(home))
+        .cloned()
+        .collect()
+}
+
+/// Rooms between two colonies' homes.
+pub fn distance(a: RoomName, b: RoomName) -> u32 {
+    screeps::game::map::get_room_linear_distance(a, b, false)
+}
@@ -1,16 +1,21 @@
 use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
-use log::warn;
-use screeps::RawObjectId;
+use log::{info, warn};
+use screeps::{
+    HasId, HasPosition, HasStore, ObjectId, RawObjectId, ResourceType, ReturnCode,
+    SharedCreepProperties, SizedRoomObject,
+};
 
-use crate::jobs::{JobProperties, JobType};
-use crate::rtb::JobBid;
+use crate::jobs::{JobClass, JobProperties, JobType};
+use crate::rtb::{JobAsk, JobBid};
+use crate::traffic;
 
 #[derive(Debug, Hash, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub struct Context {
     c_thread_id: i32,
     target_id: screeps::RawObjectId,
     t_job_type: JobType,
+    resource: Option<ResourceType>,
     world_pos: screeps::Position,
     time_remaining: u32,
     job_contribution: u32,
@@ -28,16 +33,55 @@ pub enum ContextStatus {
     Finished= 5,
 }
 
+/// Hands out the per-creep thread id contexts are keyed on, allocating one
+/// from `Memory.c_thread_next` the first time a creep is seen.
+pub fn thread_id(creep: &screeps::Creep) -> i32 {
+    let mem = screeps::SharedCreepProperties::memory(creep);
+    if let Ok(Some(cti)) = mem.i32("c_thread_id") {
+        return cti;
+    }
+
+    let root = screeps::memory::root();
+    let next = root.i32("c_thread_next").unwrap_or(None).unwrap_or(0) + 1;
+    root.set("c_thread_next", next);
+    mem.set("c_thread_id", next);
+    next
+}
+
+fn resolve<T>(id: RawObjectId) -> Option<T>
+where
+    T: HasId + SizedRoomObject,
+{
+    ObjectId::<T>::from(id).try_resolve().ok().unwrap_or(None)
+}
+
 impl Context { 
     // pub fn new(creep: &screeps::Creep, target: &dyn screeps::HasId, job_type: JobType) -> Option<Self> { 
     pub fn new(creep: &screeps::Creep, target: &dyn screeps::HasId, request: JobBid) -> Option<Self> {         
-        if !creep.has_parts_for_job(request.request) { return None };
+        Self::build(creep, target.untyped_id(), &target.pos(), request.request, request.resource)
+    }
+
+    /// Context for delivering into a sink that placed `request`.
+    pub fn deliver(creep: &screeps::Creep, request: &JobBid, pos: &screeps::Position) -> Option<Self> {
+        Self::build(creep, request.target, pos, request.request, request.resource)
+    }
+
+    /// Context for acquiring resources from a source that placed `request`.
+    pub fn acquire(creep: &screeps::Creep, request: &JobAsk, pos: &screeps::Position) -> Option<Self> {
+        Self::build(creep, request.target, pos, request.request, request.resource)
+    }
+
+    fn build(
+        creep: &screeps::Creep,
+        target_id: RawObjectId,
+        world_pos: &screeps::Position,
+        t_job_type: JobType,
+        resource: Option<ResourceType>,
+    ) -> Option<Self> {
+        if !creep.has_parts_for_job(t_job_type) { return None };
 
-        let c_thread_id;
-        let target_id = target.untyped_id();
-        let t_job_type = request.request;
-        let world_pos = target.pos();
-        let runtime = creep.job_runtime(target, request.request);
+        let world_pos = *world_pos;
+        let runtime = creep.job_runtime(&world_pos, t_job_type);
 
         let current = screeps::game::time();
 
@@ -47,23 +91,14 @@ impl Context {
         let start_tick = current.checked_add(runtime.0).unwrap_or(u32::MAX);
         let finish_tick = start_tick.checked_add(runtime.1).unwrap_or(u32::MAX);
 
-        // Get the c_thread_id from memory
-        let mem = screeps::SharedCreepProperties::memory(creep);
-        let c_thread_id_res = mem.i32("c_thread_id");
-
-        match c_thread_id_res {
-            Ok(okval) => match okval {
-                Some(cti) => c_thread_id = cti,
-                None => return None,
-            },
-            Err(_) => return None,
-        };
+        let c_thread_id = thread_id(creep);
         
         if c_thread_id > 0 && time_remaining > 0 {
             Some(Self {
                 c_thread_id,
                 target_id,
                 t_job_type,
+                resource,
                 world_pos,
                 time_remaining,
                 job_contribution,
@@ -73,6 +108,136 @@ impl Context {
             })
         } else { None }
     }
+
+    pub fn target_id(&self) -> RawObjectId { self.target_id }
+    pub fn job_type(&self) -> JobType { self.t_job_type }
+    pub fn status(&self) -> ContextStatus { self.status }
+    pub fn contribution(&self) -> u32 { self.job_contribution }
+
+    pub fn is_done(&self) -> bool {
+        match self.status {
+            ContextStatus::Finished | ContextStatus::Stopped => true,
+            _ => false,
+        }
+    }
+
+    /// Issues this tick's intent for `creep` and records the resulting status.
+    pub fn run(&mut self, creep: &screeps::Creep) -> ContextStatus {
+        let resource = self.resource.unwrap_or(ResourceType::Energy);
+
+        let code = match self.t_job_type {
+            JobType::Transfer => match resolve::<screeps::Structure>(self.target_id) {
+                Some(st) => match st.as_transferable() {
+                    Some(t) => creep.transfer_all(t, resource),
+                    None => ReturnCode::InvalidTarget,
+                },
+                None => match resolve::<screeps::Creep>(self.target_id) {
+                    Some(c) => creep.transfer_all(&c, resource),
+                    None => ReturnCode::NotFound,
+                },
+            },
+            JobType::Withdraw => match resolve::<screeps::Structure>(self.target_id) {
+                Some(st) => match st.as_withdrawable() {
+                    Some(w) => creep.withdraw_all(w, resource),
+                    None => ReturnCode::InvalidTarget,
+                },
+                None => match resolve::<screeps::Tombstone>(self.target_id) {
+                    Some(t) => creep.withdraw_all(&t, resource),
+                    None => match resolve::<screeps::Ruin>(self.target_id) {
+                        Some(r) => creep.withdraw_all(&r, resource),
+                        None => ReturnCode::NotFound,
+                    },
+                },
+            },
+            JobType::Pickup => match resolve::<screeps::Resource>(self.target_id) {
+                Some(r) => creep.pickup(&r),
+                None => ReturnCode::NotFound,
+            },
+            JobType::Harvest if creep.store_free_capacity(None) == 0
+                && creep.store_capacity(None) > 0 => ReturnCode::Full,
+            JobType::Harvest => match resolve::<screeps::Source>(self.target_id) {
+                Some(s) => creep.harvest(&s),
+                None => match resolve::<screeps::Mineral>(self.target_id) {
+                    Some(m) => creep.harvest(&m),
+                    None => match resolve::<screeps::Deposit>(self.target_id) {
+                        Some(d) => creep.harvest(&d),
+                        None => ReturnCode::NotFound,
+                    },
+                },
+            },
+            JobType::Build => match resolve::<screeps::ConstructionSite>(self.target_id) {
+                Some(cs) => creep.build(&cs),
+                None => ReturnCode::NotFound,
+            },
+            JobType::Repair => match resolve::<screeps::Structure>(self.target_id) {
+                Some(st) => creep.repair(&st),
+                None => ReturnCode::NotFound,
+            },
+            JobType::Upgrade => match resolve::<screeps::StructureController>(self.target_id) {
+                Some(ctrl) => creep.upgrade_controller(&ctrl),
+                None => ReturnCode::NotFound,
+            },
+            JobType::Heal => match resolve::<screeps::Creep>(self.target_id) {
+                Some(c) if c.hits() >= c.hits_max() => ReturnCode::Full,
+                Some(c) => match creep.heal(&c) {
+                    ReturnCode::NotInRange => creep.ranged_heal(&c),
+                    code => code,
+                },
+                None => ReturnCode::NotFound,
+            },
+            JobType::DefendR => match creep.pos().find_closest_by_range(screeps::find::HOSTILE_CREEPS) {
+                Some(h) if creep.pos().get_range_to(&self.world_pos) == 0 => creep.ranged_attack(&h),
+                Some(_) => ReturnCode::NotInRange,
+                None => ReturnCode::Full,
+            },
+            JobType::Attack => match resolve::<screeps::Structure>(self.target_id) {
+                Some(st) => creep.attack(&st),
+                None => match resolve::<screeps::Creep>(self.target_id) {
+                    Some(c) => creep.attack(&c),
+                    None => ReturnCode::Full, // target destroyed
+                },
+            },
+            JobType::Dismantle => match resolve::<screeps::Structure>(self.target_id) {
+                Some(st) => creep.dismantle(&st),
+                None => ReturnCode::Full,
+            },
+            // hold position next to the target until it goes away
+            JobType::Station => match screeps::game::get_object_erased(self.target_id) {
+                Some(_) if creep.pos().is_near_to(&self.world_pos) => ReturnCode::Ok,
+                Some(_) => ReturnCode::NotInRange,
+                None => ReturnCode::Full,
+            },
+            _ => {
+                warn!("{:?} has no executor for {:?}", creep.name(), self.t_job_type);
+                ReturnCode::InvalidArgs
+            }
+        };
+
+        self.status = match code {
+            ReturnCode::Ok => match self.t_job_type {
+                // single-action jobs are done as soon as the intent succeeds
+                JobType::Transfer | JobType::Withdraw | JobType::Pickup => ContextStatus::Finished,
+                JobType::Build | JobType::Repair | JobType::Upgrade
+                    if creep.store_used_capacity(Some(ResourceType::Energy)) == 0 => ContextStatus::Finished,
+                _ => ContextStatus::Active,
+            },
+            ReturnCode::NotInRange => {
+                match traffic::move_to(creep, &self.world_pos, 1, JobClass::Economy) {
+                    ReturnCode::NoPath => ContextStatus::Blocked,
+                    ReturnCode::Tired => ContextStatus::Waiting,
+                    _ => ContextStatus::Active,
+                }
+            }
+            ReturnCode::Tired | ReturnCode::Busy => ContextStatus::Waiting,
+            ReturnCode::NoPath => ContextStatus::Blocked,
+            ReturnCode::Full | ReturnCode::NotEnough => ContextStatus::Finished,
+            other => {
+                info!("{:?} stopped {:?}: {:?}", creep.name(), self.t_job_type, other);
+                ContextStatus::Stopped
+            }
+        };
+        self.status
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
@@ -82,7 +247,7 @@ pub struct ContextMap {
 
 impl ContextMap {
     pub fn new() -> Self { Self { map: HashMap::<RawObjectId, Context>::new() } }
-    pub fn create(mut self: Self, creep_id: &RawObjectId, context: &Context) {
+    pub fn create(self: &mut Self, creep_id: &RawObjectId, context: &Context) {
         self.map.insert(*creep_id, *context);
 
         let mem = screeps::memory::root();
@@ -124,7 +289,7 @@ impl ContextMap {
 
     }
 
-    pub fn update(mut self: Self, creep_id: &RawObjectId, context: &Context) {
+    pub fn update(self: &mut Self, creep_id: &RawObjectId, context: &Context) {
         self.map.insert(*creep_id, *context);
         let mem = screeps::memory::root();
         let mut path = "contexts.".to_string();
@@ -136,10 +301,30 @@ impl ContextMap {
             Err(e) => warn!("Serialization error: {:?}, {:?}", e, context),
         }
     }
-    pub fn delete(mut self: Self, creep_id: &RawObjectId) {
+    pub fn delete(self: &mut Self, creep_id: &RawObjectId) {
         self.map.remove(creep_id);
         let mem = screeps::memory::root();
         let mut path = "contexts.".to_string();
-        mem.del(&path);
+        path.push_str(&creep_id.to_string());
+        mem.path_del(&path);
+    }
+}
+
+/// Drops contexts of creeps that no longer exist.
+pub fn cleanup() {
+    let mem = screeps::memory::root();
+    let contexts = match mem.dict("contexts") {
+        Ok(Some(d)) => d,
+        _ => return,
+    };
+
+    for key in contexts.keys() {
+        let alive = RawObjectId::from_hex_string(&key)
+            .ok()
+            .and_then(|id| screeps::game::get_object_erased(id))
+            .is_some();
+        if !alive {
+            contexts.del(&key);
+        }
     }
 }
@@ -0,0 +1,416 @@
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use log::{debug, warn};
+use screeps::pathfinder::{
+    CostMatrix, LocalCostMatrix, MultiRoomCostResult, SearchOptions, SearchResults,
+};
+use screeps::{find, HasPosition, OwnedStructureProperties, Part, Position, RoomName, Structure};
+use serde::{Deserialize, Serialize};
+
+use crate::{routes, rtb};
+
+/// RawMemory segment the base matrices are kept in between resets.
+const SEGMENT: u32 = 10;
+
+/// Roads cost less than plain tiles so paths prefer them.
+const ROAD_COST: u8 = 1;
+pub const PLAIN_COST: u8 = 2;
+pub const SWAMP_COST: u8 = 10;
+const BLOCKED: u8 = 255;
+
+/// Added to tiles a hostile or a hazard can reach.
+const DANGER_COST: u8 = 40;
+
+/// Tiles with a creep on them, when creeps count.
+const CREEP_COST: u8 = 30;
+
+/// Base matrices are rebuilt at least this often, in case ramparts changed hands.
+const REBUILD_TICKS: u32 = 1500;
+
+/// Matrices of rooms no path went through for this long are dropped.
+const UNUSED_TICKS: u32 = 10_000;
+
+/// Most matrices kept in the segment, at about 3.4KB each.
+const MAX_STORED: usize = 25;
+
+/// Size a RawMemory segment can hold.
+const SEGMENT_LIMIT: usize = 100 * 1024;
+
+/// A room's terrain-independent costs: roads, structures and ramparts.
+/// `bits` is indexed `x * 50 + y` like the game's CostMatrix.
+#[derive(Debug, Clone)]
+struct Base {
+    bits: Vec<u8>,
+    /// `signature` of the structures it was built from; a change means it is stale
+    signature: u64,
+    built: u32,
+}
+
+/// How a base matrix is written to the segment: `bits` as base64.
+#[derive(Debug, Serialize, Deserialize)]
+struct Stored {
+    room: String,
+    bits: String,
+    #[serde(default)]
+    signature: u64,
+    built: u32,
+}
+
+#[derive(Debug, Default)]
+struct Matrices {
+    loaded: bool,
+    dirty: bool,
+    rooms: HashMap<RoomName, Base>,
+    /// tick each room's structures were last compared against its matrix
+    checked: HashMap<RoomName, u32>,
+}
+
+thread_local! {
+    static MATRICES: RefCell<Matrices> = RefCell::new(Matrices::default());
+    /// Finished matrices handed to the pathfinder this tick, by room and creeps flag.
+    static UPLOADED: RefCell<(u32, HashMap<(RoomName, bool), CostMatrix<'static>>)> =
+        RefCell::new((0, HashMap::new()));
+}
+
+fn idx(x: u32, y: u32) -> usize {
+    (x * 50 + y) as usize
+}
+
+/// Hash of where each structure is, what it is and, for ramparts, who can
+/// pass it; anything that changes a base matrix changes this. Summed so the
+/// order `find` returns them in doesn't matter.
+fn signature(structures: &[Structure]) -> u64 {
+    structures.iter().fold(0u64, |sum, s| {
+        let mut hasher = DefaultHasher::new();
+        s.pos().packed_repr().hash(&mut hasher);
+        s.structure_type().hash(&mut hasher);
+        if let Structure::Rampart(r) = s {
+            (r.my() || r.is_public()).hash(&mut hasher);
+        }
+        sum.wrapping_add(hasher.finish())
+    })
+}
+
+fn build(room: &screeps::Room, structures: &[Structure]) -> Base {
+    let mut bits = vec![0u8; 2500];
+    for s in structures {
+        let pos = s.pos();
+        let i = idx(pos.x(), pos.y());
+        match s {
+            Structure::Road(_) => {
+                if bits[i] == 0 {
+                    bits[i] = ROAD_COST;
+                }
+            }
+            Structure::Container(_) => {}
+            Structure::Rampart(r) => {
+                if !r.my() && !r.is_public() {
+                    bits[i] = BLOCKED;
+                }
+            }
+            _ => bits[i] = BLOCKED,
+        }
+    }
+    debug!("built cost matrix for {}", room.name());
+    Base {
+        bits,
+        signature: signature(structures),
+        built: screeps::game::time(),
+    }
+}
+
+fn raise(bits: &mut [u8], center: &Position, radius: u32, cost: u8) {
+    let (cx, cy) = (center.x() as i32, center.y() as i32);
+    let r = radius as i32;
+    for x in (cx - r).max(0)..=(cx + r).min(49) {
+        for y in (cy - r).max(0)..=(cy + r).min(49) {
+            let i = idx(x as u32, y as u32);
+            if bits[i] != BLOCKED {
+                bits[i] = bits[i]
+                    .max(PLAIN_COST)
+                    .saturating_add(cost)
+                    .min(BLOCKED - 1);
+            }
+        }
+    }
+}
+
+/// Costs that change every tick: hostile reach, hazards and optionally creeps.
+fn overlay(room: &screeps::Room, bits: &mut [u8], creeps: bool) {
+    for hostile in room.find(find::HOSTILE_CREEPS) {
+        let reach = if hostile.get_active_bodyparts(Part::RangedAttack) > 0 {
+            3
+        } else if hostile.get_active_bodyparts(Part::Attack) > 0 {
+            1
+        } else {
+            continue;
+        };
+        raise(bits, &hostile.pos(), reach, DANGER_COST);
+    }
+    for hazard in rtb::load_hazards(room) {
+        raise(bits, &hazard.pos, hazard.radius, DANGER_COST);
+    }
+    if creeps {
+        for creep in room.find(find::CREEPS) {
+            let pos = creep.pos();
+            let i = idx(pos.x(), pos.y());
+            if bits[i] != BLOCKED {
+                bits[i] = bits[i].max(CREEP_COST);
+            }
+        }
+    }
+}
+
+impl Matrices {
+    fn load(&mut self) {
+        if self.loaded {
+            return;
+        }
+        // only readable once the segment is active; try again next tick
+        let raw = match screeps::raw_memory::get_segment(SEGMENT) {
+            Some(raw) => raw,
+            None => return,
+        };
+        self.loaded = true;
+        if raw.is_empty() {
+            return;
+        }
+        match serde_json::from_str::<Vec<Stored>>(&raw) {
+            Ok(stored) => {
+                for s in stored {
+                    let (room, bits) = match (RoomName::new(&s.room), base64::decode(&s.bits)) {
+                        (Ok(room), Ok(bits)) if bits.len() == 2500 => (room, bits),
+                        _ => continue,
+                    };
+                    self.rooms.entry(room).or_insert(Base {
+                        bits,
+                        signature: s.signature,
+                        built: s.built,
+                    });
+                }
+            }
+            Err(e) => warn!("Deserialization error: {:?}", e),
+        }
+    }
+
+    /// Drops matrices of rooms not used lately, keeping the `MAX_STORED`
+    /// most recently used.
+    fn evict(&mut self) {
+        let time = screeps::game::time();
+        let mut used = self
+            .rooms
+            .iter()
+            .map(|(room, base)| (*room, *self.checked.get(room).unwrap_or(&base.built)))
+            .collect::<Vec<(RoomName, u32)>>();
+        used.sort_by_key(|&(_, tick)| u32::MAX - tick);
+        let keep = used
+            .iter()
+            .take(MAX_STORED)
+            .filter(|&&(_, tick)| time.saturating_sub(tick) < UNUSED_TICKS)
+            .map(|&(room, _)| room)
+            .collect::<Vec<RoomName>>();
+        if keep.len() != self.rooms.len() {
+            self.rooms.retain(|room, _| keep.contains(room));
+            self.checked.retain(|room, _| keep.contains(room));
+            self.dirty = true;
+        }
+    }
+
+    fn save(&mut self) {
+        // until the segment was read, writing it would drop what's stored there
+        if !self.loaded {
+            return;
+        }
+        self.evict();
+        if !self.dirty {
+            return;
+        }
+        let mut stored = self
+            .rooms
+            .iter()
+            .map(|(room, base)| {
+                let used = *self.checked.get(room).unwrap_or(&base.built);
+                let stored = Stored {
+                    room: room.to_string(),
+                    bits: base64::encode(&base.bits),
+                    signature: base.signature,
+                    built: base.built,
+                };
+                (used, stored)
+            })
+            .collect::<Vec<(u32, Stored)>>();
+        stored.sort_by_key(|(used, _)| u32::MAX - used);
+        let mut stored = stored.into_iter().map(|(_, s)| s).collect::<Vec<Stored>>();
+        loop {
+            match serde_json::to_string(&stored) {
+                // least recently used go first until it fits
+                Ok(k) if k.len() > SEGMENT_LIMIT && !stored.is_empty() => {
+                    stored.pop();
+                }
+                Ok(k) => {
+                    screeps::raw_memory::set_segment(SEGMENT, &k);
+                    break;
+                }
+                Err(e) => {
+                    warn!("Serialization error: {:?}", e);
+                    break;
+                }
+            }
+        }
+        self.dirty = false;
+    }
+
+    /// The base matrix for `room`, rebuilt when its structures changed.
+    fn base(&mut self, room: RoomName) -> Option<&Base> {
+        self.load();
+        let time = screeps::game::time();
+        if self.checked.insert(room, time) == Some(time) {
+            return self.rooms.get(&room);
+        }
+        if let Some(visible) = screeps::game::rooms::get(room) {
+            let structures = visible.find(find::STRUCTURES);
+            let stale = match self.rooms.get(&room) {
+                Some(b) => {
+                    b.signature != signature(&structures)
+                        || time.saturating_sub(b.built) >= REBUILD_TICKS
+                }
+                None => true,
+            };
+            if stale {
+                self.rooms.insert(room, build(&visible, &structures));
+                self.dirty = true;
+            }
+        }
+        self.rooms.get(&room)
+    }
+}
+
+/// Costs to path through `room` with, None when it was never seen.
+/// `creeps` makes occupied tiles expensive, for routing around a jam.
+fn matrix(room: RoomName, creeps: bool) -> Option<LocalCostMatrix> {
+    let mut bits = MATRICES.with(|m| m.borrow_mut().base(room).map(|b| b.bits.clone()))?;
+    if let Some(visible) = screeps::game::rooms::get(room) {
+        overlay(&visible, &mut bits, creeps);
+    }
+    let mut matrix = LocalCostMatrix::new();
+    for x in 0..50 {
+        for y in 0..50 {
+            let cost = bits[idx(x, y)];
+            if cost > 0 {
+                matrix.set(x as u8, y as u8, cost);
+            }
+        }
+    }
+    Some(matrix)
+}
+
+/// Tick the base matrix of `room` was last built, for caches derived from it.
+pub fn built(room: RoomName) -> Option<u32> {
+    MATRICES.with(|m| m.borrow_mut().base(room).map(|b| b.built))
+}
+
+/// `matrix` uploaded for the pathfinder, built once per room and tick.
+fn uploaded(room: RoomName, creeps: bool) -> Option<CostMatrix<'static>> {
+    let time = screeps::game::time();
+    UPLOADED.with(|u| {
+        let mut u = u.borrow_mut();
+        if u.0 != time {
+            *u = (time, HashMap::new());
+        }
+        if let Some(m) = u.1.get(&(room, creeps)) {
+            return Some(m.clone());
+        }
+        let m = matrix(room, creeps)?.upload();
+        u.1.insert((room, creeps), m.clone());
+        Some(m)
+    })
+}
+
+/// Rooms a search may enter.
+enum Rooms {
+    Any,
+    /// only these, in practice a planned route
+    Only(Vec<RoomName>),
+    /// these, and any other the route planner doesn't rule out
+    Passable(Vec<RoomName>),
+}
+
+/// Room callback handing our matrices to the pathfinder.
+pub fn callback(creeps: bool) -> impl FnMut(RoomName) -> MultiRoomCostResult<'static> {
+    callback_within(creeps, Rooms::Any)
+}
+
+/// `callback` that also rules out rooms outside `rooms`.
+fn callback_within(
+    creeps: bool,
+    rooms: Rooms,
+) -> impl FnMut(RoomName) -> MultiRoomCostResult<'static> {
+    move |room| {
+        let allowed = match &rooms {
+            Rooms::Any => true,
+            Rooms::Only(only) => only.contains(&room),
+            Rooms::Passable(ends) => ends.contains(&room) || routes::passable(room),
+        };
+        if !allowed {
+            return MultiRoomCostResult::Impassable;
+        }
+        match uploaded(room, creeps) {
+            Some(m) => MultiRoomCostResult::CostMatrix(m),
+            None => MultiRoomCostResult::Default,
+        }
+    }
+}
+
+/// Pathfinder search from `from` to within `range` of `to` through our
+/// matrices. Searches between rooms stay on the planned route, and never
+/// enter a room the planner rules out.
+pub fn search(from: &Position, to: &Position, range: u32, creeps: bool) -> SearchResults {
+    search_with(from, to, range, creeps, PLAIN_COST, SWAMP_COST)
+}
+
+/// `search` with terrain costs of the caller's choosing.
+pub fn search_with(
+    from: &Position,
+    to: &Position,
+    range: u32,
+    creeps: bool,
+    plain: u8,
+    swamp: u8,
+) -> SearchResults {
+    let ends = vec![from.room_name(), to.room_name()];
+    let rooms = match from.room_name() == to.room_name() {
+        true => Rooms::Passable(ends),
+        // without a route, still keep out of rooms the planner ruled out
+        false => match routes::route(from.room_name(), to.room_name()) {
+            Some(route) => Rooms::Only(route),
+            None => Rooms::Passable(ends),
+        },
+    };
+    screeps::pathfinder::search(
+        from,
+        to,
+        range,
+        SearchOptions::default()
+            .room_callback(callback_within(creeps, rooms))
+            .plain_cost(plain)
+            .swamp_cost(swamp),
+    )
+}
+
+/// Whether there is a road on `pos`, as far as its room's matrix knows.
+pub fn road(pos: &Position) -> bool {
+    MATRICES.with(|m| {
+        m.borrow_mut()
+            .base(pos.room_name())
+            .map_or(false, |b| b.bits[idx(pos.x(), pos.y())] == ROAD_COST)
+    })
+}
+
+/// Writes changed matrices to their segment and keeps it active for next tick.
+pub fn persist() {
+    screeps::raw_memory::set_active_segments(&[SEGMENT]);
+    MATRICES.with(|m| m.borrow_mut().save())
+}
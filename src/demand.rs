@@ -0,0 +1,238 @@
+use log::info;
+use screeps::{
+    find, HasPosition, HasStore, Part, Position, ResourceType, RoomName, RoomObjectProperties,
+};
+
+use crate::jobs::JobClass;
+use crate::spawning::{body_cost, Role};
+use crate::spawnqueue::{self, Policy, SpawnRequest};
+use crate::{colony, flags, meta, oracle};
+
+/// Ticks a source takes to regenerate.
+const SOURCE_REGEN_TICKS: u32 = 300;
+
+/// Ticks a creep lives; its body cost is spread over this long.
+const CREEP_LIFE_TICKS: u32 = 1500;
+
+/// Construction should be finished within this many ticks.
+const BUILD_HORIZON: u32 = 1500;
+
+/// Stored energy above this is spent upgrading over `STORAGE_HORIZON` ticks.
+const STORAGE_RESERVE: u32 = 50_000;
+const STORAGE_HORIZON: u32 = 5000;
+
+/// Upgrading WORK a level 8 controller accepts.
+const MAX_UPGRADE_WORK_RCL8: u32 = 15;
+
+/// How long a demand request may wait in the spawn queue.
+const DEMAND_DEADLINE: u32 = 300;
+
+/// Parts a room's economy needs of each kind.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Parts {
+    pub harvest_work: u32,
+    pub carry: u32,
+    pub build_work: u32,
+    pub upgrade_work: u32,
+    pub claim: u32,
+}
+
+/// Energy per tick `room`'s sources give.
+fn income(room: &screeps::Room) -> u32 {
+    room.find(find::SOURCES)
+        .iter()
+        .map(|s| s.energy_capacity() / SOURCE_REGEN_TICKS)
+        .sum()
+}
+
+/// Where haulers take energy in `room`: storage if there is one, else a spawn.
+fn drop_off(room: &screeps::Room) -> Option<Position> {
+    match room.storage() {
+        Some(s) => Some(s.pos()),
+        None => room.find(find::MY_SPAWNS).first().map(|s| s.pos()),
+    }
+}
+
+/// What `room`'s economy needs, given the upkeep of `creeps` working for it.
+pub fn target(room: &screeps::Room, creeps: &[screeps::Creep]) -> Parts {
+    let sources = room.find(find::SOURCES);
+    let drop = drop_off(room);
+
+    // each WORK harvests 2 a tick
+    let harvest_work = sources
+        .iter()
+        .map(|s| (s.energy_capacity() / SOURCE_REGEN_TICKS + 1) / 2)
+        .sum();
+
+    // everything a source makes sits in CARRY for a full round trip
+    let carry = sources
+        .iter()
+        .map(|s| {
+            let trip = drop
+                .map(|d| 2 * oracle::distance(&s.pos(), &d))
+                .unwrap_or(0);
+            let energy = s.energy_capacity() / SOURCE_REGEN_TICKS * trip;
+            (energy + 49) / 50
+        })
+        .sum();
+
+    // each WORK builds 5 a tick
+    let remaining = room
+        .find(find::MY_CONSTRUCTION_SITES)
+        .iter()
+        .map(|c| c.progress_total().saturating_sub(c.progress()))
+        .sum::<u32>();
+    let build_work = (remaining + 5 * BUILD_HORIZON - 1) / (5 * BUILD_HORIZON);
+
+    // whatever income is left after upkeep and building goes to the controller
+    let upkeep = creeps
+        .iter()
+        .map(|c| body_cost(&c.body().iter().map(|b| b.part).collect::<Vec<Part>>()))
+        .sum::<u32>()
+        / CREEP_LIFE_TICKS;
+    let stored = room
+        .storage()
+        .map(|s| s.store_of(ResourceType::Energy))
+        .unwrap_or(0);
+    let mut upgrade_work = (income(room)
+        + stored.saturating_sub(STORAGE_RESERVE) / STORAGE_HORIZON)
+        .saturating_sub(upkeep)
+        .saturating_sub(build_work * 5);
+    if room.controller().map(|c| c.level() >= 8).unwrap_or(false) {
+        upgrade_work = upgrade_work.min(MAX_UPGRADE_WORK_RCL8);
+    }
+
+    let claim = if flags::get_claim_flags().is_empty() {
+        0
+    } else {
+        1
+    };
+
+    Parts {
+        harvest_work,
+        carry,
+        build_work,
+        upgrade_work,
+        claim,
+    }
+}
+
+/// Parts `creeps` already bring, by role. Workers build first and upgrade
+/// with whatever WORK building doesn't need.
+pub fn current(creeps: &[screeps::Creep], target: &Parts) -> Parts {
+    let homes = colony::homes();
+    let mut have = Parts::default();
+    let mut worker_work = 0;
+    for c in creeps {
+        let role = match meta::of(c, &homes) {
+            Some(m) => m.role,
+            None => continue,
+        };
+        match role {
+            Role::Harvester => have.harvest_work += c.get_active_bodyparts(Part::Work),
+            Role::Hauler => have.carry += c.get_active_bodyparts(Part::Carry),
+            Role::Worker => worker_work += c.get_active_bodyparts(Part::Work),
+            Role::Upgrader => have.upgrade_work += c.get_active_bodyparts(Part::Work),
+            Role::Claimer => have.claim += 1,
+            Role::Defender | Role::Scout => {}
+        }
+    }
+    have.build_work = worker_work.min(target.build_work);
+    have.upgrade_work += worker_work - have.build_work;
+    have
+}
+
+fn request(
+    room: RoomName,
+    role: Role,
+    units: u32,
+    priority: JobClass,
+    policy: Policy,
+    target: Option<String>,
+) {
+    let time = screeps::game::time();
+    let mut spec = role.spec();
+    spec.max_units = spec.max_units.min(units.max(spec.min_units));
+    spawnqueue::enqueue(SpawnRequest {
+        spec,
+        room: room.to_string(),
+        priority,
+        deadline: time + DEMAND_DEADLINE,
+        requester: format!("demand:{}:{:?}", room, role),
+        policy,
+        queued: time,
+        not_before: 0,
+        predecessor: None,
+        target,
+    });
+}
+
+/// Queues a spawn request for each kind of part `room` is short of.
+pub fn plan(room: &screeps::Room, creeps: &[screeps::Creep]) {
+    let members = colony::members(room.name(), creeps);
+
+    let want = target(room, &members);
+    let have = current(&members, &want);
+    info!("{} demand {:?}, have {:?}", room.name(), want, have);
+
+    // too few creeps to keep extensions full means a full-size body never comes
+    let policy = if members.len() < 2 {
+        Policy::Shrink
+    } else {
+        Policy::Wait
+    };
+    let short = |want: u32, have: u32| want.saturating_sub(have);
+
+    if short(want.harvest_work, have.harvest_work) > 0 {
+        request(
+            room.name(),
+            Role::Harvester,
+            short(want.harvest_work, have.harvest_work),
+            JobClass::Economy,
+            policy,
+            None,
+        );
+    }
+    if short(want.carry, have.carry) > 0 {
+        request(
+            room.name(),
+            Role::Hauler,
+            short(want.carry, have.carry),
+            JobClass::Economy,
+            policy,
+            None,
+        );
+    }
+    if short(want.build_work, have.build_work) > 0 {
+        request(
+            room.name(),
+            Role::Worker,
+            short(want.build_work, have.build_work),
+            JobClass::Growth,
+            policy,
+            None,
+        );
+    }
+    if short(want.upgrade_work, have.upgrade_work) > 0 {
+        request(
+            room.name(),
+            Role::Upgrader,
+            short(want.upgrade_work, have.upgrade_work),
+            JobClass::Growth,
+            policy,
+            None,
+        );
+    }
+    if want.claim > have.claim && room.energy_capacity_available() >= 650 {
+        request(
+            room.name(),
+            Role::Claimer,
+            1,
+            JobClass::Growth,
+            Policy::Wait,
+            flags::get_claim_flags()
+                .first()
+                .map(|f| f.room_name().to_string()),
+        );
+    }
+}
@@ -3,7 +3,7 @@ use std::collections::HashSet;
 use log::{info, debug, warn};
 
 
-use crate::filters;
+use crate::{contexts, costmatrix, filters, ledger, oracle, paths, pricing, profiler, routes};
 
 pub fn init() -> (
     Vec<screeps::Room>,
@@ -36,14 +36,33 @@ pub fn init() -> (
 
 }
 
+/// How often profiled section costs are logged.
+const PROFILE_REPORT_INTERVAL: u32 = 100;
+
 pub fn endstep() {
     let time = screeps::game::time();
 
+    // dead creeps must give up their claims before the next assignment pass
+    ledger::cleanup();
+
     if time % 32 == 3 {
         info!("running memory cleanup");
         cleanup_memory().expect("expected Memory.creeps format to be a regular memory object");
+        pricing::cleanup();
+        contexts::cleanup();
     }
 
+    if time % PROFILE_REPORT_INTERVAL == 0 {
+        profiler::log_report();
+    }
+
+    // last, so it only gets whatever CPU the tick didn't need
+    oracle::fill();
+    costmatrix::persist();
+    ledger::save();
+    paths::save();
+    routes::save();
+
     debug!("done! cpu: {}", screeps::game::cpu::get_used());
 
 }
@@ -60,16 +60,27 @@ pub fn get_my_spawns() -> Vec<screeps::StructureSpawn> {
     screeps::game::spawns::values()
 }
 
-// pub fn get_sinks() -> Vec<dyn Sink> {
-//     todo!();
-// }
+/// Requests from every sink node in my rooms.
+pub fn get_sinks() -> Vec<JobBid> {
+    get_my_rooms()
+        .iter()
+        .flat_map(|room| nodes::discover(room).bids())
+        .collect()
+}
 
-// pub fn get_sources() -> Vec<dyn Source> {
-//     todo!();
-// }
+/// Requests from every source node in my rooms.
+pub fn get_sources() -> Vec<JobAsk> {
+    get_my_rooms()
+        .iter()
+        .flat_map(|room| nodes::discover(room).asks())
+        .collect()
+}
 
 use screeps::{HasId, Room, SharedCreepProperties};
 
+use crate::nodes;
+use crate::rtb::{JobAsk, JobBid};
+
 pub fn get_my_containers() -> Vec<screeps::StructureContainer> {
     get_my_rooms()
         .iter()
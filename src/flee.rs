@@ -0,0 +1,170 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use log::debug;
+use screeps::pathfinder::{SearchGoal, SearchOptions};
+use screeps::{
+    find, Creep, HasPosition, Part, Position, ReturnCode, RoomName, RoomObjectProperties,
+    SharedCreepProperties,
+};
+
+use crate::costmatrix::{self, PLAIN_COST, SWAMP_COST};
+use crate::jobs::JobClass;
+use crate::traffic;
+
+/// Civilians keep this many tiles beyond a hostile's reach.
+const SAFE_MARGIN: u32 = 2;
+
+/// A hostile is given one more tile of berth per this much damage a tick.
+const DAMAGE_PER_TILE: u32 = 150;
+
+/// Extra berth never goes past this, or flee paths get silly.
+const MAX_EXTRA_RANGE: u32 = 4;
+
+/// Ranged attackers hold this far from melee targets.
+pub const KITE_RANGE: u32 = 3;
+
+/// A hostile creep that can hurt us.
+#[derive(Debug, Clone)]
+pub struct Threat {
+    pub pos: Position,
+    /// how far it hits
+    pub reach: u32,
+    /// damage a tick at that range
+    pub damage: u32,
+}
+
+impl Threat {
+    pub fn of(hostile: &Creep) -> Option<Threat> {
+        let attack = hostile.get_active_bodyparts(Part::Attack);
+        let ranged = hostile.get_active_bodyparts(Part::RangedAttack);
+        let reach = match (attack, ranged) {
+            (_, r) if r > 0 => 3,
+            (a, _) if a > 0 => 1,
+            _ => return None,
+        };
+        Some(Threat {
+            pos: hostile.pos(),
+            reach,
+            damage: attack * 30 + ranged * 10,
+        })
+    }
+
+    /// How far to stay from it: its reach plus `margin`, more for heavy hitters.
+    fn berth(&self, margin: u32) -> u32 {
+        self.reach + margin + (self.damage / DAMAGE_PER_TILE).min(MAX_EXTRA_RANGE)
+    }
+}
+
+thread_local! {
+    static THREATS: RefCell<(u32, HashMap<RoomName, Vec<Threat>>)> = RefCell::new((0, HashMap::new()));
+}
+
+/// Hostiles in `room` that can hurt us, found once a tick.
+pub fn threats(room: &screeps::Room) -> Vec<Threat> {
+    let time = screeps::game::time();
+    THREATS.with(|t| {
+        let mut t = t.borrow_mut();
+        if t.0 != time {
+            *t = (time, HashMap::new());
+        }
+        t.1.entry(room.name())
+            .or_insert_with(|| {
+                room.find(find::HOSTILE_CREEPS)
+                    .iter()
+                    .filter_map(Threat::of)
+                    .collect()
+            })
+            .clone()
+    })
+}
+
+/// Steps `creep` away from `threats` until it is out of each one's berth.
+/// Ok once it is there, NoPath when there's nowhere to go.
+pub fn flee(creep: &Creep, threats: &[Threat], margin: u32, priority: JobClass) -> ReturnCode {
+    let pos = creep.pos();
+    let near = threats
+        .iter()
+        .filter(|t| t.pos.room_name() == pos.room_name())
+        .collect::<Vec<&Threat>>();
+    if near
+        .iter()
+        .all(|t| t.pos.get_range_to(&pos) >= t.berth(margin))
+    {
+        return ReturnCode::Ok;
+    }
+    let goals = near
+        .iter()
+        .map(|t| SearchGoal::new(t.pos, t.berth(margin)))
+        .collect::<Vec<SearchGoal>>();
+    if creep.fatigue() > 0 {
+        return ReturnCode::Tired;
+    }
+    let results = screeps::pathfinder::search_many(
+        &pos,
+        goals,
+        SearchOptions::default()
+            .room_callback(costmatrix::callback(false))
+            .plain_cost(PLAIN_COST)
+            .swamp_cost(SWAMP_COST)
+            .flee(true),
+    );
+    match results.path().first() {
+        Some(next) => {
+            debug!("{} flees to {}", creep.name(), next);
+            traffic::step(creep, next, priority);
+            ReturnCode::Ok
+        }
+        None => ReturnCode::NoPath,
+    }
+}
+
+/// Creeps without weapons get out of the way of hostiles near them. Returns
+/// whether `creep` is running this tick.
+pub fn retreat(creep: &Creep) -> bool {
+    if creep.get_active_bodyparts(Part::Attack) > 0
+        || creep.get_active_bodyparts(Part::RangedAttack) > 0
+    {
+        return false;
+    }
+    let room = match creep.room() {
+        Some(r) => r,
+        None => return false,
+    };
+    let threats = threats(&room);
+    let pos = creep.pos();
+    if threats
+        .iter()
+        .all(|t| t.pos.get_range_to(&pos) > t.reach + SAFE_MARGIN)
+    {
+        return false;
+    }
+    flee(creep, &threats, SAFE_MARGIN, JobClass::Emergency);
+    true
+}
+
+/// Keeps a ranged attacker at `KITE_RANGE` from `target`, backing off from
+/// anything that could reach it first, and shoots when in range.
+pub fn kite(creep: &Creep, target: &Creep) -> ReturnCode {
+    let range = creep.pos().get_range_to(target);
+    if range <= KITE_RANGE {
+        creep.ranged_attack(target);
+    }
+    let room = match creep.room() {
+        Some(r) => r,
+        None => return ReturnCode::NotInRange,
+    };
+    // only melee is worth running from; ranged hostiles reach us either way
+    let melee = threats(&room)
+        .into_iter()
+        .filter(|t| t.reach == 1 && t.pos.get_range_to(&creep.pos()) < KITE_RANGE)
+        .collect::<Vec<Threat>>();
+    if !melee.is_empty() {
+        return flee(creep, &melee, KITE_RANGE - 1, JobClass::Defense);
+    }
+    if range > KITE_RANGE {
+        return traffic::move_to(creep, &target.pos(), KITE_RANGE, JobClass::Defense);
+    }
+    traffic::park(creep, &target.pos(), KITE_RANGE, JobClass::Defense);
+    ReturnCode::Ok
+}
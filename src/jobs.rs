@@ -4,6 +4,9 @@ use screeps::{
 };
 use serde::{Deserialize, Serialize};
 
+use crate::travel::Mobility;
+use crate::{costmatrix, oracle};
+
 pub struct SearchMove {
     pub arrive_ticks: u32,
     pub search_results: Option<screeps::pathfinder::SearchResults>,
@@ -27,13 +30,36 @@ pub enum JobType {
     DefendR = 14,
     Heal = 15,
     Scout = 16,
+    Dismantle = 17,
+    Renew = 18,
+    Recycle = 19,
+}
+
+/// Width of one priority class in the assignment cost matrix. Body/distance
+/// costs never get near this, so a job in a better class always wins.
+pub const CLASS_OFFSET: u32 = 10_000;
+
+/// Priority classes; lower discriminant is more urgent.
+#[derive(Debug, Hash, Eq, PartialEq, Ord, PartialOrd, Clone, Copy, Serialize, Deserialize)]
+pub enum JobClass {
+    Emergency = 0,
+    Defense = 1,
+    Economy = 2,
+    Growth = 3,
+    Maintenance = 4,
+}
+
+impl JobClass {
+    pub fn cost_offset(&self) -> u32 {
+        *self as u32 * CLASS_OFFSET
+    }
 }
 
 pub trait JobProperties {
     fn count_bp_vec(self: &Self, part_array: Vec<screeps::Part>) -> Vec<u32>;
     fn has_parts_for_job(&self, job_type: JobType) -> bool;
     fn contribution_per_tick(&self, job_type: JobType) -> u32;
-    fn job_runtime(&self, target: &dyn HasId, job_type: JobType) -> (u32, u32, u32);
+    fn job_runtime(&self, target: &Position, job_type: JobType) -> (u32, u32, u32);
 
     fn distance_to(&self, pos: &screeps::Position) -> u32;
     fn astar(&self, target: &screeps::Position) -> SearchMove;
@@ -70,6 +96,9 @@ impl JobProperties for screeps::Creep {
             JobType::DefendR => bp_reqs = vec![Part::RangedAttack],
             JobType::Heal => bp_reqs = vec![Part::Heal],
             JobType::Scout => bp_reqs = vec![Part::Move],
+            JobType::Dismantle => bp_reqs = vec![Part::Move, Part::Work],
+            JobType::Renew => bp_reqs = vec![Part::Move],
+            JobType::Recycle => bp_reqs = vec![Part::Move],
         }
 
         bp_reqs
@@ -95,11 +124,15 @@ impl JobProperties for screeps::Creep {
             JobType::DefendR => self.get_active_bodyparts(Part::RangedAttack) * 10,
             JobType::Heal => self.get_active_bodyparts(Part::Heal) * 12,
             JobType::Scout => 1,
+            JobType::Dismantle => self.get_active_bodyparts(Part::Work) * 50,
+            JobType::Renew => 0,
+            JobType::Recycle => 0,
         }
     }
 
-    fn job_runtime(&self, target: &dyn HasId, job_type: JobType) -> (u32, u32, u32) {
-        let start_ticks = self.astar(&target.pos()).arrive_ticks;
+    fn job_runtime(&self, target: &Position, job_type: JobType) -> (u32, u32, u32) {
+        let start_ticks =
+            Mobility::of_creep(self).ticks_over(oracle::distance(&self.pos(), target));
 
         let contribution_per_tick = self.contribution_per_tick(job_type);
 
@@ -130,6 +163,9 @@ impl JobProperties for screeps::Creep {
             JobType::DefendR => self.ticks_to_live().unwrap_or(0),
             JobType::Heal => self.ticks_to_live().unwrap_or(0),
             JobType::Scout => self.ticks_to_live().unwrap_or(0),
+            JobType::Dismantle => self.ticks_to_live().unwrap_or(0),
+            JobType::Renew => 1,
+            JobType::Recycle => 1,
         };
 
         let finish_ticks = (self
@@ -148,72 +184,12 @@ impl JobProperties for screeps::Creep {
 
     /// Returns the number of ticks it will take to reach a target; roughly
     fn distance_to(&self, pos: &screeps::Position) -> u32 {
-        screeps::pathfinder::search(
-            &self.pos(),
-            pos,
-            1,
-            screeps::pathfinder::SearchOptions::default(),
-        )
-        .cost
+        costmatrix::search(&self.pos(), pos, 1, false).path().len() as u32
     }
 
-    #[inline]
-    /// calculates the fatigue required to arrive at a location
-    /// returns the number of ticks the creep will take to arrive there
+    /// Path to `pos` and the ticks this creep takes along it, load and boosts included.
     fn astar(&self, pos: &screeps::Position) -> SearchMove {
-        let mut body = self.count_bp_vec(vec![
-            Part::Attack,
-            Part::Claim,
-            Part::Heal,
-            Part::RangedAttack,
-            Part::Tough,
-            Part::Work,
-            Part::Carry,
-            Part::Move,
-        ]);
-
-        let body_move = body.pop().unwrap_or(0);
-        let body_carry = body.pop().unwrap_or(0);
-
-        if body_move == 0 && !self.pos().is_near_to(pos) {
-            return SearchMove {
-                arrive_ticks: u32::MAX,
-                search_results: None,
-            };
-        };
-
-        let carry_weight = self.store_used_capacity(None) as f32;
-        let loaded_carry = (carry_weight / 50.0).ceil() as u32;
-
-        // weight = how much gross fatigue is accumulated per move on road
-        let weight = body
-            .iter()
-            .fold(loaded_carry.min(body_carry), |acc, cur| acc + cur);
-
-        let ticks_road = (0.5 * weight as f32 / body_move as f32).ceil();
-        let ticks_plain = (1.0 * weight as f32 / body_move as f32).ceil();
-        let ticks_swamp = (5.0 * weight as f32 / body_move as f32).ceil();
-        let heuristic = self.pos().get_range_to(pos) as f32 * ticks_road;
-
-        let search_opts = screeps::pathfinder::SearchOptions::default()
-            .plain_cost(ticks_plain as u8)
-            .swamp_cost(ticks_swamp as u8)
-            .heuristic_weight(heuristic.into());
-        let results = screeps::pathfinder::search(&self.pos(), pos, 1, search_opts);
-
-        //TODO Visuals
-        SearchMove {
-            arrive_ticks: self.fatigue() / (2 * body_move)
-                + self.fatigue() % (2 * body_move)
-                + results.cost,
-            search_results: Some(results),
-        }
-        // Some(SearchMove {
-        //             fatigue_ticks: self.fatigue() / (2 * body_move) + self.fatigue() % (2 * body_move) + results.cost,
-        //             search_results: results,
-        //             path: results.load_local_path(),
-        //             incomplete: results.incomplete
-        //         })
+        Mobility::of_creep(self).search(&self.pos(), pos)
     }
 
     fn astar_move(&self, pos: &screeps::Position) {
@@ -235,7 +211,7 @@ impl JobProperties for screeps::StructureTower {
             _ => false,
         }
     }
-    fn job_runtime(&self, target: &dyn HasId, job_type: JobType) -> (u32, u32, u32) {
+    fn job_runtime(&self, target: &Position, job_type: JobType) -> (u32, u32, u32) {
         let amount = self.contribution_per_tick(job_type);
 
         let range = self.pos().get_range_to(target).min(20).max(5);
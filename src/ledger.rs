@@ -0,0 +1,178 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use log::{debug, warn};
+use screeps::RawObjectId;
+
+/// Amounts each creep has committed to a target, keyed by creep name.
+pub type Reservations = HashMap<String, u32>;
+
+/// Claims on job targets that outlive a single tick, so two creeps don't both
+/// set off to fill the same extension. Loaded from `Memory.ledger` once per
+/// tick with `take`, shared through `put` and written back by `save`.
+#[derive(Debug, Clone, Default)]
+pub struct Ledger {
+    entries: HashMap<RawObjectId, Reservations>,
+    dirty: HashSet<RawObjectId>,
+}
+
+thread_local! {
+    /// This tick's ledger and the tick it was loaded on.
+    static CURRENT: RefCell<(u32, Option<Ledger>)> = RefCell::new((0, None));
+}
+
+fn path(target: &RawObjectId) -> String {
+    let mut path = "ledger.".to_string();
+    path.push_str(&target.to_string());
+    path
+}
+
+impl Ledger {
+    fn load() -> Self {
+        let mut ledger = Self::default();
+        let mem = screeps::memory::root();
+        let keys = match mem.dict("ledger") {
+            Ok(Some(d)) => d.keys(),
+            _ => return ledger,
+        };
+
+        for key in keys {
+            let target = match RawObjectId::from_hex_string(&key) {
+                Ok(id) => id,
+                Err(_) => continue,
+            };
+            match mem.get_path::<String>(&path(&target)) {
+                Ok(Some(json)) => match serde_json::from_str::<Reservations>(&json) {
+                    Ok(r) => {
+                        ledger.entries.insert(target, r);
+                    }
+                    Err(e) => warn!("Deserialization error: {:?}", e),
+                },
+                _ => {}
+            }
+        }
+        ledger
+    }
+
+    fn save(&mut self) {
+        let mem = screeps::memory::root();
+        for target in self.dirty.drain() {
+            match self.entries.get(&target) {
+                Some(r) if !r.is_empty() => match serde_json::to_string(r) {
+                    Ok(k) => mem.path_set(&path(&target), k),
+                    Err(e) => warn!("Serialization error: {:?}", e),
+                },
+                _ => mem.path_del(&path(&target)),
+            }
+        }
+    }
+
+    /// Total committed to `target`, not counting `except`'s own claim.
+    pub fn committed(&self, target: &RawObjectId, except: Option<&str>) -> u32 {
+        self.entries
+            .get(target)
+            .map(|r| {
+                r.iter()
+                    .filter(|(name, _)| Some(name.as_str()) != except)
+                    .map(|(_, amount)| *amount)
+                    .sum()
+            })
+            .unwrap_or(0)
+    }
+
+    /// What's left of `max` at `target` for `creep` once everyone else's claims are taken out.
+    pub fn remaining(&self, target: &RawObjectId, max: u32, creep: &str) -> u32 {
+        max.saturating_sub(self.committed(target, Some(creep)))
+    }
+
+    /// Records `creep`'s claim on `target`, replacing any earlier one.
+    pub fn reserve(&mut self, target: RawObjectId, creep: &str, amount: u32) {
+        if amount == 0 {
+            return self.release(target, creep);
+        }
+        self.entries
+            .entry(target)
+            .or_insert_with(Reservations::new)
+            .insert(creep.to_string(), amount);
+        self.dirty.insert(target);
+    }
+
+    pub fn release(&mut self, target: RawObjectId, creep: &str) {
+        if let Some(r) = self.entries.get_mut(&target) {
+            if r.remove(creep).is_some() {
+                self.dirty.insert(target);
+            }
+        }
+    }
+
+    /// Drops every claim `creep` holds, e.g. before its job is decided again.
+    pub fn release_creep(&mut self, creep: &str) {
+        let targets = self
+            .entries
+            .iter()
+            .filter(|(_, r)| r.contains_key(creep))
+            .map(|(t, _)| *t)
+            .collect::<Vec<RawObjectId>>();
+        targets.into_iter().for_each(|t| self.release(t, creep));
+    }
+
+    /// Releases claims held by dead creeps or on targets that no longer exist.
+    pub fn prune(&mut self) {
+        let alive: HashSet<String> = screeps::game::creeps::keys().into_iter().collect();
+
+        for (target, r) in self.entries.iter_mut() {
+            let exists = screeps::game::get_object_erased(*target).is_some();
+            let before = r.len();
+            r.retain(|name, _| exists && alive.contains(name));
+            if r.len() != before {
+                debug!(
+                    "released {} stale reservations on {}",
+                    before - r.len(),
+                    target
+                );
+                self.dirty.insert(*target);
+            }
+        }
+    }
+}
+
+/// This tick's ledger, read from Memory on the first call of the tick. Hand
+/// it back with `put` so later users see the claims made on it.
+pub fn take() -> Ledger {
+    let time = screeps::game::time();
+    CURRENT.with(|c| {
+        let mut c = c.borrow_mut();
+        match c.1.take() {
+            Some(ledger) if c.0 == time => ledger,
+            _ => {
+                c.0 = time;
+                Ledger::load()
+            }
+        }
+    })
+}
+
+pub fn put(ledger: Ledger) {
+    let time = screeps::game::time();
+    CURRENT.with(|c| *c.borrow_mut() = (time, Some(ledger)));
+}
+
+/// Writes this tick's changed claims to `Memory.ledger`, once at the end of the tick.
+pub fn save() {
+    let time = screeps::game::time();
+    CURRENT.with(|c| {
+        let mut c = c.borrow_mut();
+        if c.0 == time {
+            if let Some(ledger) = c.1.as_mut() {
+                ledger.save();
+            }
+        }
+    })
+}
+
+/// Releases reservations of creeps that died since the last tick.
+pub fn cleanup() {
+    let mut ledger = take();
+    ledger.prune();
+    put(ledger);
+}
@@ -0,0 +1,82 @@
+use screeps::{Part, RoomObjectProperties, StructureSpawn};
+
+use crate::spawning::{body_cost, Role};
+use crate::{meta, spawnqueue};
+
+/// Bodies at least this expensive are renewed instead of replaced.
+const RENEW_MIN_COST: u32 = 1200;
+
+/// Renewal is worth walking to a spawn for below this many ticks to live.
+const RENEW_BELOW_TTL: u32 = 300;
+
+/// Spawns only renew while their room is at least this full of energy.
+const RENEW_MIN_ENERGY_RATIO: f32 = 0.8;
+
+/// A body worth less than this fraction of what its room can build at full
+/// capacity for the spec it was spawned with is recycled.
+const OBSOLETE_RATIO: f32 = 0.5;
+
+fn parts(creep: &screeps::Creep) -> Vec<Part> {
+    creep.body().iter().map(|b| b.part).collect()
+}
+
+pub fn boosted(creep: &screeps::Creep) -> bool {
+    creep.body().iter().any(|b| b.boost.is_some())
+}
+
+/// Whether `creep` is too small for its room now that bigger bodies are
+/// buildable, and something better can take its place: the room has the
+/// energy for it now, or a creep of its role is already queued.
+pub fn obsolete(creep: &screeps::Creep) -> bool {
+    if boosted(creep) {
+        return false;
+    }
+    let room = match creep.room() {
+        Some(r) => r,
+        None => return false,
+    };
+    let body = parts(creep);
+    // the spec it was asked for, caps and all; a capped body isn't a small one
+    let spec = match meta::read(creep) {
+        Some(m) => m.spec,
+        None => Role::of_body(&body).spec(),
+    };
+    let best = match spec.solve(room.energy_capacity_available()) {
+        Some((best, _)) => best,
+        None => return false,
+    };
+    if body_cost(&body) as f32 >= body_cost(&best) as f32 * OBSOLETE_RATIO {
+        return false;
+    }
+    room.energy_available() >= body_cost(&best) || spawnqueue::wanted(room.name(), spec.role)
+}
+
+/// Expensive or boosted creeps get renewed rather than replaced; CLAIM bodies
+/// can't be renewed at all.
+pub fn worth_renewing(creep: &screeps::Creep) -> bool {
+    let body = parts(creep);
+    !body.contains(&Part::Claim)
+        && (boosted(creep) || body_cost(&body) >= RENEW_MIN_COST)
+        && !obsolete(creep)
+}
+
+/// Whether `creep` should head to a spawn for renewal now.
+pub fn wants_renew(creep: &screeps::Creep) -> bool {
+    match creep.ticks_to_live() {
+        Ok(ttl) => ttl < RENEW_BELOW_TTL && worth_renewing(creep),
+        Err(_) => false,
+    }
+}
+
+/// Renewing spends the energy a spawn would build with, so only idle spawns
+/// in well stocked rooms do it.
+pub fn can_renew(spawn: &StructureSpawn) -> bool {
+    match spawn.room() {
+        Some(room) => {
+            !spawn.is_spawning()
+                && room.energy_available() as f32
+                    >= room.energy_capacity_available() as f32 * RENEW_MIN_ENERGY_RATIO
+        }
+        None => false,
+    }
+}
@@ -3,36 +3,63 @@ use log::info;
 use screeps::{game, HasPosition, Position, SharedCreepProperties};
 use stdweb::js;
 
+mod audit;
 mod bucket;
+mod colony;
 mod constructionsites;
+mod contexts;
+mod costmatrix;
 mod creeps;
+mod demand;
 mod entry;
 mod filters;
 mod flags;
+mod flee;
 mod jobs;
+mod ledger;
+mod lifecycle;
 mod logging;
+mod mcmf;
+mod meta;
+mod nodes;
+mod oracle;
+mod paths;
+mod pricing;
+mod profiler;
+mod recovery;
 mod relogic;
 mod rooms;
+mod routes;
+mod rtb;
 mod sink;
 mod source;
 mod spawning;
+mod spawnqueue;
 mod structures;
+mod stuck;
+mod succession;
+mod traffic;
+mod travel;
+mod util;
 mod world;
 
 fn main() {
     logging::setup_logging(logging::Info);
-    for creep in screeps::game::creeps::values() {
-        if creep.memory().get::<String>("role").unwrap().is_none() {
-            creep.memory().set(
-                "role",
-                creep.name().split_terminator('-').collect::<Vec<&str>>()[0],
-            );
-        }
-    }
+    // creeps spawned before metadata existed get it worked out from their bodies
+    meta::backfill();
 
     js! {
         var game_loop = @{game_loop};
 
+        // console: audit("creep name") prints its recent job decisions,
+        // audit_visual(true) draws the latest ones in each room
+        global.audit = @{console_audit};
+        global.audit_visual = @{console_audit_visual};
+        // spawn_queue() lists queued spawn requests in serving order
+        global.spawn_queue = @{console_spawn_queue};
+        // prices() lists the average bid per sink type in each room
+        global.prices = @{console_prices};
+
         module.exports.loop = function() {
             // Provide actual error traces.
             try {
@@ -52,6 +79,22 @@ fn main() {
     }
 }
 
+fn console_audit(creep: String) -> String {
+    audit::report(&creep)
+}
+
+fn console_audit_visual(on: bool) {
+    screeps::memory::root().set("audit_visual", on);
+}
+
+fn console_spawn_queue() -> String {
+    spawnqueue::report()
+}
+
+fn console_prices() -> String {
+    pricing::summary()
+}
+
 fn game_loop() {
     // info!("Starting loop...");
     let (rooms, creeps, spawns, structures, constructionsites, resources, flags, sources) =
@@ -80,8 +123,17 @@ fn game_loop() {
 
     // });
     // logic::prioritize(creeps, sources, structures, constructionsites, resources);
-    relogic::prioritize(creeps.to_vec());
+    routes::update();
+    succession::run(&creeps);
+    // Memory.strategy = "rtb" switches creep assignment over to the bid/ask market
+    match screeps::memory::root().string("strategy").unwrap_or(None).as_deref() {
+        Some("rtb") => util::calc(),
+        _ => relogic::prioritize(creeps.to_vec()),
+    }
     // bucket::bucket_sort(creeps.to_vec());
+    spawnqueue::run();
+    // every move intent is in by now
+    traffic::resolve();
 
     entry::endstep();
 }
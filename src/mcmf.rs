@@ -0,0 +1,179 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+#[derive(Debug, Copy, Clone)]
+struct Edge {
+    to: usize,
+    cap: i64,
+    flow: i64,
+    cost: i64,
+}
+
+/// Min-cost max-flow by successive shortest paths, using Dijkstra with
+/// potentials. Edge costs must be non-negative when added.
+#[derive(Debug, Clone)]
+pub struct FlowGraph {
+    graph: Vec<Vec<usize>>,
+    edges: Vec<Edge>,
+}
+
+const INF: i64 = i64::MAX / 4;
+
+impl FlowGraph {
+    pub fn new(nodes: usize) -> Self {
+        Self {
+            graph: vec![vec![]; nodes],
+            edges: vec![],
+        }
+    }
+
+    /// Adds `from -> to` and its residual edge; returns an id for `flow`.
+    pub fn add_edge(&mut self, from: usize, to: usize, cap: i64, cost: i64) -> usize {
+        let id = self.edges.len();
+        self.edges.push(Edge {
+            to,
+            cap,
+            flow: 0,
+            cost,
+        });
+        self.edges.push(Edge {
+            to: from,
+            cap: 0,
+            flow: 0,
+            cost: -cost,
+        });
+        self.graph[from].push(id);
+        self.graph[to].push(id + 1);
+        id
+    }
+
+    pub fn flow(&self, edge: usize) -> i64 {
+        self.edges[edge].flow
+    }
+
+    /// Pushes as much flow as fits from `source` to `sink` at the least total
+    /// cost; returns (flow, cost).
+    pub fn solve(&mut self, source: usize, sink: usize) -> (i64, i64) {
+        let n = self.graph.len();
+        let mut potential = vec![0_i64; n];
+        let (mut total_flow, mut total_cost) = (0, 0);
+
+        loop {
+            let mut dist = vec![INF; n];
+            let mut prev: Vec<Option<usize>> = vec![None; n];
+            let mut heap = BinaryHeap::new();
+            dist[source] = 0;
+            heap.push(Reverse((0, source)));
+
+            while let Some(Reverse((d, u))) = heap.pop() {
+                if d > dist[u] {
+                    continue;
+                }
+                for &e in &self.graph[u] {
+                    let edge = self.edges[e];
+                    if edge.cap - edge.flow <= 0 {
+                        continue;
+                    }
+                    let nd = d + edge.cost + potential[u] - potential[edge.to];
+                    if nd < dist[edge.to] {
+                        dist[edge.to] = nd;
+                        prev[edge.to] = Some(e);
+                        heap.push(Reverse((nd, edge.to)));
+                    }
+                }
+            }
+
+            if dist[sink] == INF {
+                break;
+            }
+            for v in 0..n {
+                if dist[v] < INF {
+                    potential[v] += dist[v];
+                }
+            }
+
+            let mut push = INF;
+            let mut v = sink;
+            while let Some(e) = prev[v] {
+                push = push.min(self.edges[e].cap - self.edges[e].flow);
+                v = self.edges[e ^ 1].to;
+            }
+
+            let mut v = sink;
+            while let Some(e) = prev[v] {
+                self.edges[e].flow += push;
+                self.edges[e ^ 1].flow -= push;
+                v = self.edges[e ^ 1].to;
+            }
+
+            total_flow += push;
+            total_cost += push * (potential[sink] - potential[source]);
+        }
+
+        (total_flow, total_cost)
+    }
+}
+
+/// Gives each agent at most one task at the least total cost, task `j` taking
+/// up to `slots[j]` agents. `costs` is row-major, one row per agent, None where
+/// an agent can't take a task. Leaving an agent idle costs more than any task,
+/// so the flow only ever goes where it lowers the total.
+pub fn assign(costs: &[Option<u32>], slots: &[u32]) -> Vec<Option<usize>> {
+    let width = slots.len();
+    if width == 0 {
+        return vec![];
+    }
+    let height = costs.len() / width;
+    let (source, sink) = (0, 1);
+    let agent = |i: usize| 2 + i;
+    let task = |j: usize| 2 + height + j;
+    let idle = u32::MAX as i64 + 1;
+
+    let mut graph = FlowGraph::new(2 + height + width);
+    let mut pairs = vec![];
+    for i in 0..height {
+        graph.add_edge(source, agent(i), 1, 0);
+        graph.add_edge(agent(i), sink, 1, idle);
+        for j in 0..width {
+            if let Some(cost) = costs[i * width + j] {
+                pairs.push((i, j, graph.add_edge(agent(i), task(j), 1, cost as i64)));
+            }
+        }
+    }
+    for (j, &n) in slots.iter().enumerate() {
+        graph.add_edge(task(j), sink, n as i64, 0);
+    }
+    graph.solve(source, sink);
+
+    let mut out = vec![None; height];
+    for (i, j, edge) in pairs {
+        if graph.flow(edge) > 0 {
+            out[i] = Some(j);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::JobClass;
+
+    #[test]
+    fn emergency_transfer_beats_harvest() {
+        // a WORK+CARRY creep: harvesting is closer and suits its body better,
+        // but the spawn refill is an emergency
+        let harvest = JobClass::Economy.cost_offset() + 40;
+        let transfer = JobClass::Emergency.cost_offset() + 150;
+        assert_eq!(
+            assign(&[Some(harvest), Some(transfer)], &[5, 1]),
+            vec![Some(1)]
+        );
+    }
+
+    #[test]
+    fn full_jobs_leave_creeps_idle() {
+        let costs = [Some(10), Some(20)];
+        assert_eq!(assign(&costs, &[1]), vec![Some(0), None]);
+    }
+}
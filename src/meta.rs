@@ -0,0 +1,102 @@
+use log::warn;
+use screeps::memory::MemoryReference;
+use screeps::{Part, RoomName, RoomObjectProperties, SharedCreepProperties};
+
+use crate::colony;
+use crate::spawning::{BodySpec, Role};
+
+/// Who a creep is, written into its memory at spawn and read back with `read`.
+/// Keys are flat (`role`, `home`, ...) so they stay readable from the console.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreepMeta {
+    pub role: Role,
+    /// the colony it works for
+    pub home: RoomName,
+    /// where it was sent, when that isn't home
+    pub target: Option<RoomName>,
+    pub spec: BodySpec,
+    /// how many creeps held this post before it
+    pub generation: u32,
+    pub spawned: u32,
+}
+
+pub fn write(mem: &MemoryReference, meta: &CreepMeta) {
+    mem.set("role", meta.role.name());
+    mem.set("home", meta.home.to_string());
+    match meta.target {
+        Some(t) => mem.set("target", t.to_string()),
+        None => mem.del("target"),
+    }
+    match serde_json::to_string(&meta.spec) {
+        Ok(k) => mem.set("spec", k),
+        Err(e) => warn!("Serialization error: {:?}", e),
+    }
+    mem.set("generation", meta.generation as i32);
+    mem.set("spawned", meta.spawned as i32);
+}
+
+fn room(mem: &MemoryReference, key: &str) -> Option<RoomName> {
+    mem.string(key)
+        .unwrap_or(None)
+        .and_then(|n| RoomName::new(&n).ok())
+}
+
+/// The metadata `creep` was spawned with; None for creeps from before it existed.
+pub fn read(creep: &screeps::Creep) -> Option<CreepMeta> {
+    let mem = creep.memory();
+    let role = Role::from_name(&mem.string("role").unwrap_or(None)?)?;
+    let spec = match mem.string("spec") {
+        Ok(Some(json)) => serde_json::from_str::<BodySpec>(&json).ok()?,
+        _ => return None,
+    };
+    Some(CreepMeta {
+        role,
+        home: room(&mem, "home")?,
+        target: room(&mem, "target"),
+        spec,
+        generation: mem.i32("generation").unwrap_or(None).unwrap_or(0) as u32,
+        spawned: mem.i32("spawned").unwrap_or(None).unwrap_or(0) as u32,
+    })
+}
+
+/// Metadata for a creep spawned without any, worked out from its body and
+/// where it is.
+pub fn infer(creep: &screeps::Creep, homes: &[RoomName]) -> Option<CreepMeta> {
+    let body = creep.body().iter().map(|b| b.part).collect::<Vec<Part>>();
+    let role = Role::of_body(&body);
+    let mut spec = role.spec();
+    spec.max_units = spec
+        .unit
+        .first()
+        .map(|u| body.iter().filter(|&p| p == u).count() as u32)
+        .unwrap_or(0)
+        .max(spec.min_units);
+    Some(CreepMeta {
+        role,
+        home: creep
+            .room()
+            .and_then(|r| colony::home_of(r.name(), homes))?,
+        target: None,
+        spec,
+        generation: 0,
+        spawned: 0,
+    })
+}
+
+/// Writes inferred metadata for every creep that has none.
+pub fn backfill() {
+    let homes = colony::homes();
+    for creep in screeps::game::creeps::values() {
+        if read(&creep).is_some() {
+            continue;
+        }
+        if let Some(meta) = infer(&creep, &homes) {
+            write(&creep.memory(), &meta);
+        }
+    }
+}
+
+/// `read`, falling back to `infer`.
+pub fn of(creep: &screeps::Creep, homes: &[RoomName]) -> Option<CreepMeta> {
+    read(creep).or_else(|| infer(creep, homes))
+}
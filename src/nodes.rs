@@ -0,0 +1,177 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use screeps::{find, Room, RoomName, Structure};
+
+use crate::rtb::{JobAsk, JobBid, SinkNode, SourceNode};
+
+/// What a finder gets to look at: the room plus a structure list fetched once
+/// for every finder that needs it.
+pub struct Scan<'a> {
+    pub room: &'a Room,
+    pub structures: Vec<Structure>,
+}
+
+pub type SinkFinder = fn(&Scan) -> Vec<Box<dyn SinkNode>>;
+pub type SourceFinder = fn(&Scan) -> Vec<Box<dyn SourceNode>>;
+
+/// Every sink and source node found in one room this tick.
+pub struct RoomNodes {
+    pub sinks: Vec<Box<dyn SinkNode>>,
+    pub sources: Vec<Box<dyn SourceNode>>,
+}
+
+impl RoomNodes {
+    pub fn bids(&self) -> Vec<JobBid> {
+        self.sinks.iter().filter_map(|n| n.sink_request()).collect()
+    }
+
+    pub fn asks(&self) -> Vec<JobAsk> {
+        self.sources
+            .iter()
+            .filter_map(|n| n.source_request())
+            .collect()
+    }
+}
+
+/// The finders rooms are scanned with. A new node type only needs a
+/// `register_*` call; nothing else has to know about it.
+pub struct Registry {
+    sinks: Vec<SinkFinder>,
+    sources: Vec<SourceFinder>,
+}
+
+/// Boxes one `find` constant's results as `$node` trait objects.
+macro_rules! found {
+    ($node:ident, $find:expr) => {
+        |scan: &Scan| {
+            scan.room
+                .find($find)
+                .into_iter()
+                .map(|o| Box::new(o) as Box<dyn $node>)
+                .collect()
+        }
+    };
+}
+
+/// Boxes the scanned structures of the given variant as `$node` trait objects.
+macro_rules! structures {
+    ($node:ident, $variant:ident) => {
+        |scan: &Scan| {
+            scan.structures
+                .iter()
+                .filter_map(|st| match st {
+                    Structure::$variant(s) => Some(Box::new(s.clone()) as Box<dyn $node>),
+                    _ => None,
+                })
+                .collect()
+        }
+    };
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self {
+            sinks: vec![],
+            sources: vec![],
+        }
+    }
+
+    pub fn register_sinks(&mut self, finder: SinkFinder) -> &mut Self {
+        self.sinks.push(finder);
+        self
+    }
+
+    pub fn register_sources(&mut self, finder: SourceFinder) -> &mut Self {
+        self.sources.push(finder);
+        self
+    }
+
+    pub fn scan(&self, room: &Room) -> RoomNodes {
+        let scan = Scan {
+            room,
+            structures: room.find(find::STRUCTURES),
+        };
+        RoomNodes {
+            sinks: self.sinks.iter().flat_map(|f| f(&scan)).collect(),
+            sources: self.sources.iter().flat_map(|f| f(&scan)).collect(),
+        }
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry
+            .register_sinks(found!(SinkNode, find::MY_CREEPS))
+            .register_sinks(found!(SinkNode, find::MY_CONSTRUCTION_SITES))
+            .register_sinks(structures!(SinkNode, Container))
+            .register_sinks(structures!(SinkNode, Controller))
+            .register_sinks(structures!(SinkNode, Extension))
+            .register_sinks(structures!(SinkNode, Extractor))
+            .register_sinks(structures!(SinkNode, Factory))
+            .register_sinks(structures!(SinkNode, Lab))
+            .register_sinks(structures!(SinkNode, Link))
+            .register_sinks(structures!(SinkNode, Nuker))
+            .register_sinks(structures!(SinkNode, Observer))
+            .register_sinks(structures!(SinkNode, PowerSpawn))
+            .register_sinks(structures!(SinkNode, Rampart))
+            .register_sinks(structures!(SinkNode, Road))
+            .register_sinks(structures!(SinkNode, Spawn))
+            .register_sinks(structures!(SinkNode, Storage))
+            .register_sinks(structures!(SinkNode, Terminal))
+            .register_sinks(structures!(SinkNode, Tower))
+            .register_sinks(structures!(SinkNode, Wall))
+            .register_sinks(structures!(SinkNode, PowerBank))
+            .register_sinks(structures!(SinkNode, InvaderCore));
+        registry
+            .register_sources(found!(SourceNode, find::MY_CREEPS))
+            .register_sources(found!(SourceNode, find::SOURCES))
+            .register_sources(found!(SourceNode, find::MINERALS))
+            .register_sources(found!(SourceNode, find::DEPOSITS))
+            .register_sources(found!(SourceNode, find::DROPPED_RESOURCES))
+            .register_sources(found!(SourceNode, find::TOMBSTONES))
+            .register_sources(found!(SourceNode, find::RUINS))
+            .register_sources(structures!(SourceNode, Container))
+            .register_sources(structures!(SourceNode, Extension))
+            .register_sources(structures!(SourceNode, Factory))
+            .register_sources(structures!(SourceNode, Lab))
+            .register_sources(structures!(SourceNode, Link))
+            .register_sources(structures!(SourceNode, Nuker))
+            .register_sources(structures!(SourceNode, PowerSpawn))
+            .register_sources(structures!(SourceNode, Spawn))
+            .register_sources(structures!(SourceNode, Storage))
+            .register_sources(structures!(SourceNode, Terminal))
+            .register_sources(structures!(SourceNode, Tower))
+            .register_sources(structures!(SourceNode, PowerBank));
+        registry
+    }
+}
+
+thread_local! {
+    static REGISTRY: RefCell<Registry> = RefCell::new(Registry::default());
+    static CACHE: RefCell<HashMap<RoomName, (u32, Rc<RoomNodes>)>> = RefCell::new(HashMap::new());
+}
+
+/// Adds finders to the registry used by `discover`.
+pub fn register(f: impl FnOnce(&mut Registry)) {
+    REGISTRY.with(|r| f(&mut r.borrow_mut()));
+    CACHE.with(|c| c.borrow_mut().clear());
+}
+
+/// Nodes in `room`, scanned at most once per tick.
+pub fn discover(room: &Room) -> Rc<RoomNodes> {
+    let time = screeps::game::time();
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        match cache.get(&room.name()) {
+            Some((tick, nodes)) if *tick == time => Rc::clone(nodes),
+            _ => {
+                let nodes = Rc::new(REGISTRY.with(|r| r.borrow().scan(room)));
+                cache.insert(room.name(), (time, Rc::clone(&nodes)));
+                nodes
+            }
+        }
+    })
+}
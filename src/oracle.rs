@@ -0,0 +1,214 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use log::{debug, warn};
+use screeps::{find, HasPosition, Position, RoomName};
+
+use crate::{costmatrix, filters};
+
+/// Stop solving pairs once this fraction of the tick's CPU limit is used.
+const FILL_CPU_FRACTION: f64 = 0.8;
+
+/// Don't solve pairs while the bucket is below this.
+const FILL_MIN_BUCKET: i64 = 2000;
+
+/// Solved pairs are solved again after this long, in case roads or walls changed.
+const PAIR_TTL: u32 = 20_000;
+
+/// Travel cost between landmarks (spawns, sources, controllers, storage and
+/// room exits), solved with the pathfinder whenever there is CPU to spare and
+/// kept in `Memory.oracle` between resets.
+#[derive(Debug, Default)]
+struct Oracle {
+    loaded: bool,
+    dirty: bool,
+    /// (a, b) packed positions with a < b -> (cost, tick solved)
+    pairs: HashMap<(i32, i32), (u32, u32)>,
+    /// pairs someone asked about that aren't solved yet
+    wanted: HashSet<(i32, i32)>,
+    landmarks: HashMap<RoomName, Vec<Position>>,
+    landmarks_tick: u32,
+}
+
+thread_local! {
+    static ORACLE: RefCell<Oracle> = RefCell::new(Oracle::default());
+}
+
+fn key(a: &Position, b: &Position) -> (i32, i32) {
+    let (a, b) = (a.packed_repr(), b.packed_repr());
+    (a.min(b), a.max(b))
+}
+
+/// Points worth knowing real travel costs between in `room`.
+pub fn landmarks(room: &screeps::Room) -> Vec<Position> {
+    let mut points = vec![];
+    points.extend(room.find(find::MY_SPAWNS).iter().map(|s| s.pos()));
+    points.extend(room.find(find::SOURCES).iter().map(|s| s.pos()));
+    points.extend(room.controller().map(|c| c.pos()));
+    points.extend(room.storage().map(|s| s.pos()));
+    // the middle tile of each exit side stands in for the whole side
+    for exit in [
+        find::EXIT_TOP,
+        find::EXIT_RIGHT,
+        find::EXIT_BOTTOM,
+        find::EXIT_LEFT,
+    ]
+    .iter()
+    {
+        let tiles = room.find(*exit);
+        if let Some(mid) = tiles.get(tiles.len() / 2) {
+            points.push(*mid);
+        }
+    }
+    points
+}
+
+impl Oracle {
+    fn load(&mut self) {
+        if self.loaded {
+            return;
+        }
+        self.loaded = true;
+        let mem = screeps::memory::root();
+        if let Ok(Some(json)) = mem.string("oracle") {
+            match serde_json::from_str::<Vec<(i32, i32, u32, u32)>>(&json) {
+                Ok(pairs) => {
+                    self.pairs = pairs
+                        .into_iter()
+                        .map(|(a, b, cost, tick)| ((a, b), (cost, tick)))
+                        .collect()
+                }
+                Err(e) => warn!("Deserialization error: {:?}", e),
+            }
+        }
+    }
+
+    fn save(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        let pairs = self
+            .pairs
+            .iter()
+            .map(|(&(a, b), &(cost, tick))| (a, b, cost, tick))
+            .collect::<Vec<(i32, i32, u32, u32)>>();
+        match serde_json::to_string(&pairs) {
+            Ok(k) => screeps::memory::root().set("oracle", k),
+            Err(e) => warn!("Serialization error: {:?}", e),
+        }
+        self.dirty = false;
+    }
+
+    fn refresh_landmarks(&mut self) {
+        let time = screeps::game::time();
+        if self.landmarks_tick == time && !self.landmarks.is_empty() {
+            return;
+        }
+        self.landmarks = filters::get_my_rooms()
+            .iter()
+            .map(|r| (r.name(), landmarks(r)))
+            .collect();
+        self.landmarks_tick = time;
+    }
+
+    /// The landmark nearest `pos` in its room, however far.
+    fn snap(&self, pos: &Position) -> Option<Position> {
+        self.landmarks
+            .get(&pos.room_name())?
+            .iter()
+            .min_by_key(|l| l.get_range_to(pos))
+            .copied()
+    }
+
+    fn distance(&mut self, from: &Position, to: &Position) -> u32 {
+        let range = from.get_range_to(to);
+        self.load();
+        self.refresh_landmarks();
+
+        match (self.snap(from), self.snap(to)) {
+            // ends closer to each other than to their landmarks learn nothing from them
+            (Some(a), Some(b)) if a != b && range > from.get_range_to(&a) + to.get_range_to(&b) => {
+                match self.pairs.get(&key(&a, &b)) {
+                    // the walk to and from the landmarks on top; never below range
+                    Some((cost, _)) => {
+                        (cost + from.get_range_to(&a) + to.get_range_to(&b)).max(range)
+                    }
+                    None => {
+                        self.wanted.insert(key(&a, &b));
+                        range
+                    }
+                }
+            }
+            _ => range,
+        }
+    }
+
+    /// Queues every landmark pair in each room that is missing or stale.
+    fn want_all(&mut self) {
+        let time = screeps::game::time();
+        for points in self.landmarks.values() {
+            for (i, a) in points.iter().enumerate() {
+                for b in points.iter().skip(i + 1) {
+                    let k = key(a, b);
+                    match self.pairs.get(&k) {
+                        Some((_, tick)) if time.saturating_sub(*tick) < PAIR_TTL => {}
+                        _ => {
+                            self.wanted.insert(k);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn fill(&mut self) {
+        self.load();
+        self.refresh_landmarks();
+        if self.wanted.is_empty() {
+            self.want_all();
+        }
+
+        let budget = screeps::game::cpu::limit() as f64 * FILL_CPU_FRACTION;
+        let mut solved = 0;
+        while (screeps::game::cpu::bucket() as i64) >= FILL_MIN_BUCKET
+            && screeps::game::cpu::get_used() < budget
+        {
+            let k = match self.wanted.iter().next() {
+                Some(k) => *k,
+                None => break,
+            };
+            self.wanted.remove(&k);
+
+            let (a, b) = (Position::from_packed(k.0), Position::from_packed(k.1));
+            let results = costmatrix::search(&a, &b, 1, false);
+            if !results.incomplete {
+                // path length, not cost: matrix costs are scaled and callers want tiles
+                let tiles = results.path().len() as u32;
+                self.pairs.insert(k, (tiles, screeps::game::time()));
+                self.dirty = true;
+                solved += 1;
+            }
+        }
+
+        if solved > 0 {
+            debug!(
+                "oracle solved {} pairs, {} waiting",
+                solved,
+                self.wanted.len()
+            );
+        }
+        self.save();
+    }
+}
+
+/// Travel cost from `from` to `to`: the solved path between the landmarks
+/// nearest each end plus the range to them, when that pair is solved; range
+/// otherwise. Unknown pairs get queued for `fill`.
+pub fn distance(from: &Position, to: &Position) -> u32 {
+    ORACLE.with(|o| o.borrow_mut().distance(from, to))
+}
+
+/// Solves queued pairs with whatever CPU is left this tick.
+pub fn fill() {
+    ORACLE.with(|o| o.borrow_mut().fill())
+}
@@ -0,0 +1,260 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use log::{debug, warn};
+use screeps::{Creep, HasPosition, Position, RoomName, SharedCreepProperties};
+use serde::{Deserialize, Serialize};
+
+use crate::costmatrix;
+
+/// Paths nobody walked for this long are dropped.
+const PATH_TTL: u32 = 1500;
+
+/// A path's last use is written back at most this often; well inside
+/// `PATH_TTL`, so a reset never loses enough of it to drop a path in use.
+const USED_RESOLUTION: u32 = 100;
+
+/// Tile offsets for directions 1 (top) to 8 (top left), clockwise.
+const OFFSETS: [(i32, i32); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+
+/// A path shared by every creep walking between the same two points.
+/// Each room it crosses is one segment: the packed position it enters at
+/// and a string of direction digits from there.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Cached {
+    segments: Vec<(i32, String)>,
+    /// cost matrix build tick of each room it crosses; a rebuild drops the path
+    stamps: Vec<(String, u32)>,
+    used: u32,
+}
+
+impl Cached {
+    fn new(path: &[Position]) -> Cached {
+        let mut segments: Vec<(i32, String)> = vec![];
+        for (i, pos) in path.iter().enumerate() {
+            let prev = match i {
+                0 => None,
+                _ => path.get(i - 1),
+            };
+            match prev {
+                Some(p) if p.room_name() == pos.room_name() => {
+                    if let (Some(seg), Some(dir)) = (segments.last_mut(), p.get_direction_to(pos)) {
+                        seg.1
+                            .push(std::char::from_digit(dir as u32, 10).unwrap_or('0'));
+                    }
+                }
+                _ => segments.push((pos.packed_repr(), String::new())),
+            }
+        }
+        let mut rooms = path
+            .iter()
+            .map(|p| p.room_name())
+            .collect::<Vec<RoomName>>();
+        rooms.dedup();
+        Cached {
+            segments,
+            stamps: rooms
+                .into_iter()
+                .filter_map(|r| costmatrix::built(r).map(|t| (r.to_string(), t)))
+                .collect(),
+            used: screeps::game::time(),
+        }
+    }
+
+    fn positions(&self) -> Vec<Position> {
+        let mut out = vec![];
+        for (start, dirs) in self.segments.iter() {
+            let mut pos = Position::from_packed(*start);
+            out.push(pos);
+            for d in dirs.chars() {
+                let (dx, dy) = match d.to_digit(10) {
+                    Some(d) if d >= 1 && d <= 8 => OFFSETS[d as usize - 1],
+                    _ => break,
+                };
+                let (x, y) = (pos.x() as i32 + dx, pos.y() as i32 + dy);
+                if x < 0 || y < 0 || x > 49 || y > 49 {
+                    break;
+                }
+                pos = Position::new(x as u32, y as u32, pos.room_name());
+                out.push(pos);
+            }
+        }
+        out
+    }
+
+    /// Whether no room it crosses got a new cost matrix since it was found.
+    fn fresh(&self) -> bool {
+        self.stamps.iter().all(|(room, built)| {
+            match RoomName::new(room).ok().and_then(costmatrix::built) {
+                Some(now) => now == *built,
+                None => true,
+            }
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct Paths {
+    loaded: bool,
+    dirty: bool,
+    /// "<to>:<range>:" -> "<from>" -> path; creeps remember the two joined
+    paths: HashMap<String, HashMap<String, Cached>>,
+}
+
+thread_local! {
+    static PATHS: RefCell<Paths> = RefCell::new(Paths::default());
+}
+
+fn dest_key(to: &Position, range: u32) -> String {
+    format!("{}:{}:", to.packed_repr(), range)
+}
+
+/// A creep's "<to>:<range>:<from>" key split into destination and origin.
+fn split(key: &str) -> Option<(&str, &str)> {
+    let at = key.rfind(':')? + 1;
+    Some((&key[..at], &key[at..]))
+}
+
+impl Paths {
+    fn load(&mut self) {
+        if self.loaded {
+            return;
+        }
+        self.loaded = true;
+        if let Ok(Some(json)) = screeps::memory::root().string("paths") {
+            match serde_json::from_str::<HashMap<String, HashMap<String, Cached>>>(&json) {
+                Ok(paths) => self.paths = paths,
+                Err(e) => warn!("Deserialization error: {:?}", e),
+            }
+        }
+    }
+
+    fn save(&mut self) {
+        let time = screeps::game::time();
+        let mut pruned = false;
+        self.paths.retain(|_, from| {
+            let before = from.len();
+            from.retain(|_, c| time.saturating_sub(c.used) < PATH_TTL);
+            pruned |= from.len() != before;
+            !from.is_empty()
+        });
+        if pruned {
+            self.dirty = true;
+        }
+        if !self.dirty {
+            return;
+        }
+        match serde_json::to_string(&self.paths) {
+            Ok(k) => screeps::memory::root().set("paths", k),
+            Err(e) => warn!("Serialization error: {:?}", e),
+        }
+        self.dirty = false;
+    }
+
+    /// Where the path under `key` goes after `pos`, looking from `step` on.
+    /// None once the creep has drifted off it or it went stale.
+    fn follow(&mut self, key: &str, pos: &Position, step: usize) -> Option<(usize, Position)> {
+        let (dest, from) = split(key)?;
+        let paths = self.paths.get_mut(dest)?;
+        let cached = paths.get_mut(from)?;
+        if !cached.fresh() {
+            debug!("dropping stale path {}", key);
+            paths.remove(from);
+            self.dirty = true;
+            return None;
+        }
+        let positions = cached.positions();
+        // a creep is on its last step, or one further when the move went through
+        let at = (step.saturating_sub(1)..(step + 2).min(positions.len()))
+            .find(|&i| positions[i] == *pos)?;
+        let next = *positions.get(at + 1)?;
+        let time = screeps::game::time();
+        if time.saturating_sub(cached.used) >= USED_RESOLUTION {
+            cached.used = time;
+            self.dirty = true;
+        }
+        Some((at, next))
+    }
+
+    /// A fresh path to `dest` that passes through `pos`.
+    fn shared(&self, dest: &str, pos: &Position) -> Option<(String, usize)> {
+        self.paths.get(dest)?.iter().find_map(|(from, c)| {
+            let positions = c.positions();
+            let at = positions.iter().position(|p| p == pos)?;
+            if at + 1 < positions.len() && c.fresh() {
+                Some((format!("{}{}", dest, from), at))
+            } else {
+                None
+            }
+        })
+    }
+
+    fn next_step(&mut self, creep: &Creep, target: &Position, range: u32) -> Option<Position> {
+        self.load();
+        let pos = creep.pos();
+        let dest = dest_key(target, range);
+        let mem = creep.memory();
+
+        if let Ok(Some(key)) = mem.path_string("path.key") {
+            if key.starts_with(&dest) {
+                let step = mem.path_i32("path.step").unwrap_or(None).unwrap_or(0) as usize;
+                if let Some((at, next)) = self.follow(&key, &pos, step) {
+                    mem.path_set("path.step", at as i32);
+                    return Some(next);
+                }
+            }
+            mem.path_del("path");
+        }
+
+        if let Some((key, at)) = self.shared(&dest, &pos) {
+            if let Some((at, next)) = self.follow(&key, &pos, at) {
+                mem.path_set("path.key", key);
+                mem.path_set("path.step", at as i32);
+                return Some(next);
+            }
+        }
+
+        let results = costmatrix::search(&pos, target, range, false);
+        let mut path = vec![pos];
+        path.extend(results.path());
+        let next = *path.get(1)?;
+        // partial paths only get the creep started
+        if !results.incomplete {
+            let from = pos.packed_repr().to_string();
+            let key = format!("{}{}", dest, from);
+            self.paths
+                .entry(dest)
+                .or_default()
+                .insert(from, Cached::new(&path));
+            self.dirty = true;
+            mem.path_set("path.key", key);
+            mem.path_set("path.step", 0);
+        }
+        Some(next)
+    }
+}
+
+/// The next tile for `creep` on its way to within `range` of `target`,
+/// reusing a cached path when it is on one.
+pub fn next_step(creep: &Creep, target: &Position, range: u32) -> Option<Position> {
+    PATHS.with(|p| p.borrow_mut().next_step(creep, target, range))
+}
+
+/// Drops the path `creep` is following so its next move searches again.
+pub fn forget(creep: &Creep) {
+    creep.memory().path_del("path");
+}
+
+/// Prunes unused paths and writes the rest to `Memory.paths`.
+pub fn save() {
+    PATHS.with(|p| p.borrow_mut().save())
+}
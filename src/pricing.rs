@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+use log::{info, warn};
+use screeps::{HasId, RawObjectId};
+use serde::{Deserialize, Serialize};
+
+use crate::filters;
+use crate::rtb::SinkSources;
+
+/// Number of past prices kept per sink.
+const HISTORY_LEN: usize = 20;
+
+/// Percent a bid rises for every tick its request goes unfilled.
+const RAISE_PCT: u32 = 10;
+
+/// Percent a bid falls for every tick it is being served.
+const DECAY_PCT: u32 = 5;
+
+/// Percent of the way back to its opening price a bid nobody placed since
+/// the last cleanup moves.
+const RELAX_PCT: u32 = 25;
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PriceRecord {
+    pub ty: SinkSources,
+    pub price: u32,
+    pub last_tick: u32,
+    pub history: Vec<u32>,
+}
+
+/// Opening price for a sink type; these were the old static bids.
+pub fn base(ty: SinkSources) -> u32 {
+    match ty {
+        SinkSources::Link => 20,
+        SinkSources::Storage => 5,
+        SinkSources::Tower => 3,
+        SinkSources::Spawn => 6,
+        SinkSources::Container => 5,
+        SinkSources::PowerSpawn => 10,
+        _ => 1,
+    }
+}
+
+/// Sink types whose bid comes from this engine rather than a formula.
+pub fn is_priced(ty: SinkSources) -> bool {
+    matches!(
+        ty,
+        SinkSources::Link
+            | SinkSources::Storage
+            | SinkSources::Tower
+            | SinkSources::Spawn
+            | SinkSources::Container
+            | SinkSources::PowerSpawn
+    )
+}
+
+/// (floor, ceiling) a sink type's price is clamped to.
+pub fn bounds(ty: SinkSources) -> (u32, u32) {
+    match ty {
+        SinkSources::Link => (5, 60),
+        SinkSources::Storage => (1, 20),
+        SinkSources::Tower => (3, 80),
+        SinkSources::Spawn => (6, 100),
+        SinkSources::Container => (2, 30),
+        SinkSources::PowerSpawn => (5, 40),
+        _ => (1, 50),
+    }
+}
+
+fn path(sink_id: &RawObjectId) -> String {
+    let mut path = "prices.".to_string();
+    path.push_str(&sink_id.to_string());
+    path
+}
+
+pub fn read(sink_id: &RawObjectId) -> Option<PriceRecord> {
+    let mem = screeps::memory::root();
+    match mem.get_path::<String>(&path(sink_id)) {
+        Ok(Some(json)) => match serde_json::from_str::<PriceRecord>(&json) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                warn!("Deserialization error: {:?}", e);
+                None
+            }
+        },
+        _ => None,
+    }
+}
+
+fn write(sink_id: &RawObjectId, record: &PriceRecord) {
+    let mem = screeps::memory::root();
+    match serde_json::to_string(record) {
+        Ok(k) => mem.path_set(&path(sink_id), k),
+        Err(e) => warn!("Serialization error: {:?}", e),
+    }
+}
+
+/// Current bid for a sink, falling back to the type's opening price.
+pub fn quote(sink_id: RawObjectId, ty: SinkSources) -> u32 {
+    let (floor, ceiling) = bounds(ty);
+    read(&sink_id)
+        .map(|r| r.price)
+        .unwrap_or_else(|| base(ty))
+        .max(floor)
+        .min(ceiling)
+}
+
+/// Moves a sink's price once per tick: up while its request sits unfilled,
+/// down while someone is serving it.
+pub fn observe(sink_id: RawObjectId, ty: SinkSources, served: bool) {
+    let time = screeps::game::time();
+    let (floor, ceiling) = bounds(ty);
+
+    let mut record = read(&sink_id).unwrap_or(PriceRecord {
+        ty,
+        price: base(ty),
+        last_tick: 0,
+        history: vec![],
+    });
+
+    if record.last_tick == time {
+        return;
+    }
+
+    let price = if served {
+        record.price - record.price * DECAY_PCT / 100
+    } else {
+        record.price + (record.price * RAISE_PCT / 100).max(1)
+    };
+
+    record.history.push(record.price);
+    if record.history.len() > HISTORY_LEN {
+        record.history.remove(0);
+    }
+    record.price = price.max(floor).min(ceiling);
+    record.last_tick = time;
+
+    write(&sink_id, &record);
+}
+
+/// Average current price of every priced sink type in `room`.
+pub fn report(room: &screeps::Room) -> Vec<(SinkSources, u32)> {
+    let mut totals: HashMap<SinkSources, (u32, u32)> = HashMap::new();
+
+    room.find(screeps::find::STRUCTURES)
+        .iter()
+        .filter_map(|st| read(&st.untyped_id()))
+        .for_each(|record| {
+            let entry = totals.entry(record.ty).or_insert((0, 0));
+            entry.0 += record.price;
+            entry.1 += 1;
+        });
+
+    let mut report = totals
+        .iter()
+        .map(|(ty, (sum, count))| (*ty, sum / count))
+        .collect::<Vec<(SinkSources, u32)>>();
+    report.sort_by_key(|(ty, _)| *ty as u32);
+    report
+}
+
+pub fn log_report(room: &screeps::Room) {
+    for (ty, price) in report(room) {
+        info!("{:?} {:?}: {}", room.name(), ty, price);
+    }
+}
+
+/// `report` for every room of mine, one line per sink type.
+pub fn summary() -> String {
+    let lines = filters::get_my_rooms()
+        .iter()
+        .flat_map(|room| {
+            let name = room.name();
+            report(room)
+                .into_iter()
+                .map(move |(ty, price)| format!("{} {:?}: {}", name, ty, price))
+        })
+        .collect::<Vec<String>>();
+    if lines.is_empty() {
+        return "no prices recorded".to_string();
+    }
+    lines.join("\n")
+}
+
+/// Moves a price that saw no bid last tick part of the way back to its
+/// opening price, so it doesn't stay where it was when the bid went away.
+fn relax(sink_id: &RawObjectId, mut record: PriceRecord) {
+    if record.last_tick + 1 >= screeps::game::time() {
+        return;
+    }
+    let gap = record.price as i64 - base(record.ty) as i64;
+    if gap == 0 {
+        return;
+    }
+    let step = match gap * RELAX_PCT as i64 / 100 {
+        0 => gap.signum(),
+        s => s,
+    };
+    record.history.push(record.price);
+    if record.history.len() > HISTORY_LEN {
+        record.history.remove(0);
+    }
+    record.price = (record.price as i64 - step) as u32;
+    write(sink_id, &record);
+}
+
+/// Drops price records for sinks that no longer exist and relaxes those
+/// that nobody bid with lately.
+pub fn cleanup() {
+    let mem = screeps::memory::root();
+    let prices = match mem.dict("prices") {
+        Ok(Some(d)) => d,
+        _ => return,
+    };
+
+    for key in prices.keys() {
+        let alive = RawObjectId::from_hex_string(&key)
+            .ok()
+            .and_then(|id| screeps::game::get_object_erased(id))
+            .is_some();
+        if !alive {
+            prices.del(&key);
+            continue;
+        }
+        if let Ok(id) = RawObjectId::from_hex_string(&key) {
+            if let Some(record) = read(&id) {
+                relax(&id, record);
+            }
+        }
+    }
+}
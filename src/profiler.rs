@@ -0,0 +1,114 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+/// Weight of the newest sample in a section's running average.
+const SMOOTHING: f64 = 0.1;
+
+/// Pairs of sections doing the same work two ways, reported side by side.
+const COMPARISONS: &[(&str, &str)] = &[("assign_full", "assign_incremental")];
+
+/// Running CPU cost of one named section, kept at `Memory.profile.<label>`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileRecord {
+    pub avg: f64,
+    pub last: f64,
+    pub count: u32,
+    /// Running average of the section's result quality, lower is better.
+    #[serde(default)]
+    pub quality: Option<f64>,
+}
+
+fn path(label: &str) -> String {
+    let mut path = "profile.".to_string();
+    path.push_str(label);
+    path
+}
+
+pub fn read(label: &str) -> Option<ProfileRecord> {
+    let mem = screeps::memory::root();
+    match mem.get_path::<String>(&path(label)) {
+        Ok(Some(json)) => serde_json::from_str::<ProfileRecord>(&json).ok(),
+        _ => None,
+    }
+}
+
+fn write(label: &str, record: &ProfileRecord) {
+    match serde_json::to_string(record) {
+        Ok(k) => screeps::memory::root().path_set(&path(label), k),
+        Err(e) => warn!("Serialization error: {:?}", e),
+    }
+}
+
+fn record(label: &str, cpu: f64) {
+    let record = match read(label) {
+        Some(r) => ProfileRecord {
+            avg: r.avg * (1.0 - SMOOTHING) + cpu * SMOOTHING,
+            last: cpu,
+            count: r.count + 1,
+            quality: r.quality,
+        },
+        None => ProfileRecord {
+            avg: cpu,
+            last: cpu,
+            count: 1,
+            quality: None,
+        },
+    };
+    write(label, &record);
+}
+
+/// Folds the quality of the latest run of `label` into its record.
+pub fn score(label: &str, quality: f64) {
+    if let Some(mut r) = read(label) {
+        r.quality = Some(match r.quality {
+            Some(q) => q * (1.0 - SMOOTHING) + quality * SMOOTHING,
+            None => quality,
+        });
+        write(label, &r);
+    }
+}
+
+/// Runs `f`, charging the CPU it used to `label`.
+pub fn measure<T>(label: &str, f: impl FnOnce() -> T) -> T {
+    let start = screeps::game::cpu::get_used();
+    let result = f();
+    record(label, screeps::game::cpu::get_used() - start);
+    result
+}
+
+pub fn log_report() {
+    let mem = screeps::memory::root();
+    let labels = match mem.dict("profile") {
+        Ok(Some(d)) => d.keys(),
+        _ => return,
+    };
+    for label in labels {
+        if let Some(r) = read(&label) {
+            info!(
+                "{}: avg {:.2} cpu, last {:.2} ({} samples)",
+                label, r.avg, r.last, r.count
+            );
+        }
+    }
+    for (a, b) in COMPARISONS {
+        if let (Some(ra), Some(rb)) = (read(a), read(b)) {
+            info!(
+                "{} vs {}: {:.2} vs {:.2} cpu per run, quality {} vs {} ({} vs {} runs)",
+                a,
+                b,
+                ra.avg,
+                rb.avg,
+                quality(&ra),
+                quality(&rb),
+                ra.count,
+                rb.count
+            );
+        }
+    }
+}
+
+fn quality(r: &ProfileRecord) -> String {
+    r.quality
+        .map(|q| format!("{:.0}", q))
+        .unwrap_or_else(|| "-".to_string())
+}
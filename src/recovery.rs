@@ -0,0 +1,115 @@
+use log::{info, warn};
+use screeps::{look, Part, Position, RoomName, StructureProperties, StructureType};
+
+use crate::jobs::{JobClass, JobType};
+use crate::spawning::Role;
+use crate::spawnqueue::{self, Policy, SpawnRequest};
+use crate::{colony, demand, meta};
+
+/// A room without harvesters or haulers and less energy than this can't
+/// spawn its way back through the normal demand model.
+const RECOVERY_ENERGY: u32 = 550;
+
+/// Bootstrap creeps kept alive while a room recovers.
+const RECOVERY_CREEPS: usize = 2;
+
+/// Recovery ends once this fraction of the harvesting target is back, or
+/// the room has `RECOVERY_ENERGY` to spawn a proper harvester with.
+const RECOVERY_EXIT_RATIO: f32 = 0.5;
+
+/// How long a bootstrap request may wait in the spawn queue.
+const RECOVERY_DEADLINE: u32 = 300;
+
+fn path(room: RoomName) -> String {
+    format!("recovery.{}", room)
+}
+
+/// Whether `room` is recovering from losing its economy creeps.
+pub fn active(room: RoomName) -> bool {
+    screeps::memory::root().path_bool(&path(room))
+}
+
+/// Rooms currently recovering.
+pub fn rooms() -> Vec<RoomName> {
+    colony::homes().into_iter().filter(|&r| active(r)).collect()
+}
+
+/// WORK and CARRY bringing energy in: harvesters and haulers, plus the
+/// workers recovery spawns to do both.
+fn economy(members: &[screeps::Creep]) -> (u32, u32) {
+    let homes = colony::homes();
+    members
+        .iter()
+        .filter_map(|c| meta::of(c, &homes).map(|m| (c, m.role)))
+        .fold((0, 0), |(work, carry), (c, role)| match role {
+            Role::Harvester => (work + c.get_active_bodyparts(Part::Work), carry),
+            Role::Hauler => (work, carry + c.get_active_bodyparts(Part::Carry)),
+            Role::Worker => (
+                work + c.get_active_bodyparts(Part::Work),
+                carry + c.get_active_bodyparts(Part::Carry),
+            ),
+            _ => (work, carry),
+        })
+}
+
+/// Enters or leaves recovery for `room` given the creeps working for it.
+/// It enters with no WORK or no CARRY left and leaves only once both are
+/// back and there is harvesting or energy to spare, so it doesn't flap.
+pub fn update(room: &screeps::Room, members: &[screeps::Creep]) {
+    let want = demand::target(room, members);
+    let (work, carry) = economy(members);
+    let name = room.name();
+    let threshold = RECOVERY_ENERGY.min(room.energy_capacity_available());
+
+    let starved = (work == 0 || carry == 0) && room.energy_available() < threshold;
+    let restored = work > 0
+        && carry > 0
+        && (work as f32 >= want.harvest_work as f32 * RECOVERY_EXIT_RATIO
+            || room.energy_available() >= threshold);
+
+    let mem = screeps::memory::root();
+    if !active(name) && starved {
+        warn!("{} lost its economy, recovering", name);
+        mem.path_set(&path(name), true);
+        // normal requests would sit waiting for energy nobody brings
+        spawnqueue::cancel(&format!("demand:{}:", name));
+    } else if active(name) && restored {
+        info!("{} recovered", name);
+        mem.path_del(&path(name));
+    }
+}
+
+/// Queues the cheapest creep that can both harvest and deliver.
+pub fn spawn(room: &screeps::Room, members: &[screeps::Creep]) {
+    if members.len() >= RECOVERY_CREEPS {
+        return;
+    }
+    let time = screeps::game::time();
+    let mut spec = Role::Worker.spec();
+    spec.max_units = spec.min_units;
+    spawnqueue::enqueue(SpawnRequest {
+        spec,
+        room: room.name().to_string(),
+        priority: JobClass::Emergency,
+        deadline: time + RECOVERY_DEADLINE,
+        requester: format!("recovery:{}", room.name()),
+        policy: Policy::Shrink,
+        queued: time,
+        not_before: 0,
+        predecessor: None,
+        target: None,
+    });
+}
+
+/// Jobs a recovering room still runs: harvesting, picking up and filling
+/// spawns and extensions. Upgrading, building and everything else waits.
+pub fn allows(job: JobType, target: &Position) -> bool {
+    match job {
+        JobType::Harvest | JobType::Pickup => true,
+        JobType::Transfer => target.look_for(look::STRUCTURES).iter().any(|s| {
+            s.structure_type() == StructureType::Spawn
+                || s.structure_type() == StructureType::Extension
+        }),
+        _ => false,
+    }
+}
@@ -1,11 +1,19 @@
-use log::info;
+use std::collections::HashMap;
+
+use log::{info, warn};
 use screeps::{
-    find, look, Attackable, Creep, HasPosition, HasStore, OwnedStructureProperties, Position,
-    ResourceType, RoomObjectProperties, SharedCreepProperties, StructureProperties,
+    find, look, Attackable, Creep, HasId, HasPosition, HasStore, OwnedStructureProperties,
+    Position, RawObjectId, ResourceType, RoomName, RoomObjectProperties, SharedCreepProperties,
+    StructureProperties,
 };
+use serde::{Deserialize, Serialize};
 
-use crate::jobs::{JobProperties, JobType};
-use crate::{bucket, filters, flags, spawning};
+use crate::jobs::{JobClass, JobProperties, JobType, CLASS_OFFSET};
+use crate::ledger::{self, Ledger};
+use crate::{
+    audit, bucket, colony, filters, flags, flee, lifecycle, mcmf, oracle, profiler, recovery,
+    spawning, stuck, succession, traffic,
+};
 
 // pub fn select_upgraders(mut creeps: Vec<Creep>) -> Vec<Creep> {
 //     creeps.retain(|c| {
@@ -130,35 +138,152 @@ use crate::{bucket, filters, flags, spawning};
 
 //     unempty
 // }
-#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Context {
     job: JobType,
     target: Position,
+    class: JobClass,
+    /// What the ledger tracks claims against; None for jobs without a fixed capacity.
+    target_id: Option<RawObjectId>,
+    /// How much the target still needs (or holds), in the units creeps reserve.
+    demand: u32,
+}
+
+impl Context {
+    pub fn target(&self) -> Position {
+        self.target
+    }
+
+    pub fn class(&self) -> JobClass {
+        self.class
+    }
+
+    pub fn job(&self) -> JobType {
+        self.job
+    }
+
+    /// Same job on the same target, ignoring how much is left of it.
+    fn same_job(&self, other: &Context) -> bool {
+        self.job == other.job && self.target == other.target && self.target_id == other.target_id
+    }
+}
+
+/// Every this many ticks all creeps are matched again, not just the free ones.
+const FULL_SOLVE_INTERVAL: u32 = 20;
+
+/// Idle creeps are only lent out for jobs at least this urgent...
+const LEND_MAX_CLASS: JobClass = JobClass::Defense;
+
+/// ...in colonies whose home is at most this many rooms away.
+const LEND_RANGE: u32 = 2;
+
+/// Energy a controller wants per tick of upgrading, scaled up when nothing is being built.
+const UPGRADE_DEMAND: u32 = 300;
+
+/// What `c` would commit to a `job` target, in the units that job's demand is
+/// counted in: energy (or resource amount) for everything with a capacity.
+fn offer(c: &Creep, job: JobType) -> u32 {
+    match job {
+        JobType::Transfer => c.store_used_capacity(None),
+        JobType::Pickup | JobType::Withdraw => c.store_free_capacity(None).max(0) as u32,
+        JobType::Build | JobType::Repair | JobType::Upgrade => {
+            c.store_used_capacity(Some(ResourceType::Energy))
+        }
+        // energy mined over one regeneration cycle
+        JobType::Harvest => c.get_active_bodyparts(screeps::Part::Work) * 2 * 300,
+        _ => 1,
+    }
+}
+
+/// A higher-class job within this range may pull a creep off its current job.
+const PREEMPT_RANGE: u32 = 10;
+
+/// Charged for leaving a job that is still valid; larger than any class gap so
+/// committed creeps only move when preempted.
+const SWITCH_PENALTY: u32 = CLASS_OFFSET * 5;
+
+/// Spawns and extensions below this fraction of capacity are emergency refills.
+const EMERGENCY_ENERGY_RATIO: f32 = 0.5;
+
+/// Controllers closer than this to downgrading get emergency upgrades.
+const EMERGENCY_DOWNGRADE_TICKS: u32 = 5000;
+
+fn is_under_attack(room: &screeps::Room) -> bool {
+    !room.find(find::HOSTILE_CREEPS).is_empty() || !room.find(find::HOSTILE_POWER_CREEPS).is_empty()
+}
+
+fn refill_class(st: &screeps::Structure) -> JobClass {
+    let room = match st.room() {
+        Some(r) => r,
+        None => return JobClass::Economy,
+    };
+    match st.structure_type() {
+        screeps::StructureType::Spawn | screeps::StructureType::Extension => {
+            if (room.energy_available() as f32)
+                < room.energy_capacity_available() as f32 * EMERGENCY_ENERGY_RATIO
+            {
+                JobClass::Emergency
+            } else {
+                JobClass::Economy
+            }
+        }
+        screeps::StructureType::Tower if is_under_attack(&room) => JobClass::Defense,
+        _ => JobClass::Economy,
+    }
+}
+
+pub fn read_commitment(creep: &Creep) -> Option<Context> {
+    match creep.memory().string("job") {
+        Ok(Some(json)) => serde_json::from_str::<Context>(&json).ok(),
+        _ => None,
+    }
+}
+
+fn write_commitment(creep: &Creep, ctx: Option<&Context>) {
+    match ctx {
+        Some(ctx) => match serde_json::to_string(ctx) {
+            Ok(json) => creep.memory().set("job", json),
+            Err(e) => warn!("Serialization error: {:?}", e),
+        },
+        None => creep.memory().del("job"),
+    }
+}
+
+/// Whether a higher-class job with room left has opened up close to `creep`.
+fn preempted(creep: &Creep, current: &Context, contexts: &[Context], ledger: &Ledger) -> bool {
+    contexts.iter().any(|ctx| {
+        ctx.class < current.class
+            && ctx.target.get_range_to(creep) <= PREEMPT_RANGE
+            && job_cost(creep, ctx).is_some()
+            && ctx
+                .target_id
+                .map(|id| ledger.remaining(&id, ctx.demand, &creep.name()) > 0)
+                .unwrap_or(true)
+    })
+}
+
+/// Extra cost of moving `creep` from its committed job onto `ctx`.
+fn switch_cost(creep: &Creep, current: Option<&Context>, ctx: &Context) -> u32 {
+    match current {
+        None => 0,
+        Some(cur) if cur.same_job(ctx) => 0,
+        Some(cur) if ctx.class < cur.class && ctx.target.get_range_to(creep) <= PREEMPT_RANGE => 0,
+        Some(_) => SWITCH_PENALTY,
+    }
 }
 
 pub fn get_harvest_jobs(mut creeps: Vec<screeps::Creep>) -> Vec<Context> {
     let mut sources = filters::get_my_sources();
     sources.retain(|s| s.energy() > 0 || s.ticks_to_regeneration() < 20);
 
-    // Holds a vector of positions near the source
-    let source_slots = sources
-        .iter()
-        .map(|s| {
-            s.pos()
-                .neighbors()
-                .iter()
-                .filter(|&pos| pos.move_cost().is_some())
-                .map(|&pos| pos)
-                .collect::<Vec<Position>>()
-        })
-        .flatten()
-        .collect::<Vec<Position>>();
-
-    source_slots
+    sources
         .iter()
         .map(|s| Context {
             job: JobType::Harvest,
-            target: *s,
+            target: s.pos(),
+            class: JobClass::Economy,
+            target_id: Some(s.untyped_id()),
+            demand: s.energy_capacity(),
         })
         .collect::<Vec<Context>>()
 }
@@ -198,33 +323,30 @@ pub fn get_transfer_jobs(mut creeps: Vec<Creep>) -> Vec<Context> {
                 },
             },
         })
-        .flat_map(|d| {
-            [Context {
+        .map(|d| {
+            let demand = d
+                .as_has_store()
+                .map(|o| o.store_free_capacity(Some(ResourceType::Energy)).max(0) as u32)
+                .unwrap_or(0);
+            Context {
                 job: JobType::Transfer,
                 target: d.pos(),
-            }]
-            .repeat(match d.structure_type() {
-                screeps::StructureType::Spawn => 2,
-                screeps::StructureType::Extension => 1,
-                screeps::StructureType::Link => 2,
-                screeps::StructureType::Storage => 3,
-                screeps::StructureType::Tower => 2,
-                screeps::StructureType::Lab => 1,
-                screeps::StructureType::Factory => 1,
-                _ => 0,
-            })
+                class: refill_class(d),
+                target_id: Some(d.untyped_id()),
+                demand,
+            }
         })
         .collect::<Vec<Context>>()
 }
 pub fn get_gather_jobs(mut creeps: Vec<Creep>) -> Vec<Context> {
     let mut contexts = filters::get_groundscores()
         .iter()
-        .flat_map(|g| {
-            [Context {
-                job: JobType::Pickup,
-                target: g.pos(),
-            }]
-            .repeat((g.amount() / 100).max(1) as usize)
+        .map(|g| Context {
+            job: JobType::Pickup,
+            target: g.pos(),
+            class: JobClass::Economy,
+            target_id: Some(g.untyped_id()),
+            demand: g.amount(),
         })
         .collect::<Vec<Context>>();
 
@@ -234,6 +356,9 @@ pub fn get_gather_jobs(mut creeps: Vec<Creep>) -> Vec<Context> {
             contexts.push(Context {
                 job: JobType::Withdraw,
                 target: r.pos(),
+                class: JobClass::Economy,
+                target_id: Some(r.untyped_id()),
+                demand: r.store_used_capacity(None),
             })
         });
         let tomb = r.find(find::TOMBSTONES);
@@ -241,6 +366,9 @@ pub fn get_gather_jobs(mut creeps: Vec<Creep>) -> Vec<Context> {
             contexts.push(Context {
                 job: JobType::Withdraw,
                 target: r.pos(),
+                class: JobClass::Economy,
+                target_id: Some(r.untyped_id()),
+                demand: r.store_used_capacity(None),
             })
         });
     });
@@ -249,17 +377,22 @@ pub fn get_gather_jobs(mut creeps: Vec<Creep>) -> Vec<Context> {
 }
 
 pub fn get_upgrade_jobs(mut creeps: Vec<Creep>) -> Vec<Context> {
+    let demand = match filters::get_my_buildables().len() > 0 {
+        true => UPGRADE_DEMAND,
+        false => UPGRADE_DEMAND * 3,
+    };
     filters::get_my_controllers()
         .iter()
-        .flat_map(|c| {
-            [Context {
-                job: JobType::Upgrade,
-                target: c.pos(),
-            }]
-            .repeat(match filters::get_my_buildables().len() > 0 {
-                true => 1,
-                false => 3,
-            })
+        .map(|c| Context {
+            job: JobType::Upgrade,
+            target: c.pos(),
+            class: if c.ticks_to_downgrade() < EMERGENCY_DOWNGRADE_TICKS {
+                JobClass::Emergency
+            } else {
+                JobClass::Growth
+            },
+            target_id: Some(c.untyped_id()),
+            demand,
         })
         .collect::<Vec<Context>>()
 }
@@ -295,11 +428,14 @@ pub fn get_build_jobs(mut creeps: Vec<Creep>) -> Vec<Context> {
     });
 
     if let Some(c) = buildables.first() {
-        return [Context {
+        let demand = c.progress_total() - c.progress();
+        return vec![Context {
             job: JobType::Build,
             target: c.pos(),
-        }]
-        .repeat(5);
+            class: JobClass::Growth,
+            target_id: Some(c.untyped_id()),
+            demand,
+        }];
     } else {
         return vec![];
     }
@@ -314,11 +450,26 @@ pub fn get_repair_jobs(mut creeps: Vec<Creep>) -> Vec<Context> {
         return vec![];
     }
     if let Some(c) = filters::get_my_repairables().first() {
-        return [Context {
+        let class = match c.structure_type() {
+            screeps::StructureType::Rampart | screeps::StructureType::Wall
+                if c.room().map(|r| is_under_attack(&r)).unwrap_or(false) =>
+            {
+                JobClass::Defense
+            }
+            _ => JobClass::Maintenance,
+        };
+        // one energy repairs 100 hits
+        let demand = c
+            .as_attackable()
+            .map(|a| (a.hits_max() - a.hits()) / 100)
+            .unwrap_or(0);
+        return vec![Context {
             job: JobType::Repair,
             target: c.pos(),
-        }]
-        .repeat(3);
+            class,
+            target_id: Some(c.untyped_id()),
+            demand,
+        }];
     } else {
         return vec![];
     }
@@ -343,10 +494,42 @@ pub fn get_scout_jobs(mut creeps: Vec<Creep>) -> Vec<Context> {
         .map(|pos| Context {
             job: JobType::Claim,
             target: *pos,
+            class: JobClass::Growth,
+            target_id: None,
+            demand: 1,
         })
         .collect::<Vec<Context>>()
 }
 
+/// Renewal and recycling at each spawn. The spawn is the ledger target, so
+/// it serves one creep at a time; that creep is taken whole, and costs
+/// next to nothing, so it goes ahead of other economy work.
+pub fn get_lifecycle_jobs(creeps: Vec<Creep>) -> Vec<Context> {
+    let renew = creeps.iter().any(|c| lifecycle::wants_renew(c));
+    let recycle = creeps.iter().any(|c| lifecycle::obsolete(c));
+    if !renew && !recycle {
+        return vec![];
+    }
+
+    let mut contexts = vec![];
+    for spawn in filters::get_my_spawns().iter() {
+        let context = |job: JobType| Context {
+            job,
+            target: spawn.pos(),
+            class: JobClass::Economy,
+            target_id: Some(spawn.untyped_id()),
+            demand: 1,
+        };
+        if renew && lifecycle::can_renew(spawn) {
+            contexts.push(context(JobType::Renew));
+        }
+        if recycle {
+            contexts.push(context(JobType::Recycle));
+        }
+    }
+    contexts
+}
+
 pub fn get_defense_jobs(mut creeps: Vec<Creep>, room: &screeps::Room) -> Vec<Creep> {
     let (hc, hpc, hsp, hst, hcs) = filters::get_hostility(&room);
     if hc.len() > 0 || hpc.len() > 0 || hsp.len() > 0 || hst.len() > 0 || hcs.len() > 0 {
@@ -442,23 +625,34 @@ pub fn get_defense_jobs(mut creeps: Vec<Creep>, room: &screeps::Room) -> Vec<Cre
                     let s = &ramparts[i];
 
                     match h.pos().get_range_to(&s.pos()) {
-                        0 => match h.pos().find_closest_by_range(screeps::find::HOSTILE_CREEPS) {
-                            Some(hc) => match h.ranged_attack(&hc) {
-                                screeps::ReturnCode::Ok => {
-                                    assigned_defenders.push(h);
-                                }
-                                _ => {}
-                            },
-                            None => {}
-                        },
+                        0 => {
+                            if let Some(hc) =
+                                h.pos().find_closest_by_range(screeps::find::HOSTILE_CREEPS)
+                            {
+                                h.ranged_attack(&hc);
+                            }
+                        }
                         _ => {
-                            h.move_to(&s.pos());
+                            traffic::move_to(h, &s.pos(), 0, JobClass::Defense);
                         }
                     }
+                    // holding or heading for a rampart; it has its move for the tick
+                    assigned_defenders.push(h);
                 });
             defenders.retain(|&c| !assigned_defenders.contains(&c));
         }
 
+        // ranged defenders left without a rampart kite the closest hostile
+        defenders
+            .iter()
+            .filter(|&c| c.room().map(|r| r.name()) == Some(room.name()))
+            .for_each(|&h| {
+                if let Some(enemy) = h.pos().find_closest_by_range(screeps::find::HOSTILE_CREEPS) {
+                    flee::kite(h, &enemy);
+                    assigned_defenders.push(h);
+                }
+            });
+
         while healers.len() > 0 && assigned_healers.len() < hurt.len() {
             let width = healers.len();
             let height = hurt.len();
@@ -499,7 +693,7 @@ pub fn get_defense_jobs(mut creeps: Vec<Creep>, room: &screeps::Room) -> Vec<Cre
                             }
                             screeps::ReturnCode::NotInRange => {
                                 assigned_healers.push(h);
-                                h.move_to(&s.pos());
+                                traffic::move_to(h, &s.pos(), 1, JobClass::Defense);
                             }
                             _ => {}
                         },
@@ -614,9 +808,238 @@ pub fn assign_harvesters(mut creeps: Vec<screeps::Creep>) -> Vec<screeps::Creep>
     assigned_harvesters
 }
 
-pub fn prioritize(mut creeps: Vec<screeps::Creep>) {
-    let total = creeps.len();
+/// Why a creep-job pair costs what it does. The assignment cost is the sum;
+/// kept apart so the audit trail can show which term decided.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CostParts {
+    pub body: i32,
+    pub distance: i32,
+    pub store: i32,
+    pub priority: i32,
+    pub switch: i32,
+}
+
+impl CostParts {
+    pub fn total(&self) -> u32 {
+        (self.body as i64
+            + self.distance as i64
+            + self.store as i64
+            + self.priority as i64
+            + self.switch as i64)
+            .max(0)
+            .min(u32::MAX as i64) as u32
+    }
+}
 
+/// Body/distance/store cost of `c` doing `ctx`, or `None` if it can't do the job right now.
+fn job_cost(c: &Creep, ctx: &Context) -> Option<CostParts> {
+    // a creep that got stuck on its way there is given something else
+    if stuck::blocked(c, &ctx.target) {
+        return None;
+    }
+    let parts = |p: screeps::Part| c.get_active_bodyparts(p) as i32;
+    let dist = oracle::distance(&c.pos(), &ctx.target) as i32;
+    let moves = parts(screeps::Part::Move).max(1);
+    let energy = c.store_used_capacity(Some(ResourceType::Energy));
+
+    let cost = |body: i32, distance: i32| {
+        Some(CostParts {
+            body,
+            distance,
+            ..CostParts::default()
+        })
+    };
+
+    match ctx.job {
+        // a creep that can carry delivers once it's full instead of dropping
+        JobType::Harvest
+            if c.has_parts_for_job(JobType::Harvest)
+                && (c.store_capacity(None) == 0 || c.store_free_capacity(None) > 0) =>
+        {
+            cost(
+                (100 - parts(screeps::Part::Work) * 5).max(0) + parts(screeps::Part::Move),
+                dist * 3,
+            )
+        }
+        JobType::Transfer
+            if c.has_parts_for_job(JobType::Transfer) && c.store_used_capacity(None) > 0 =>
+        {
+            // a fuller creep is worth sending further
+            let used = c.store_used_capacity(None) as i32;
+            Some(CostParts {
+                body: 100 - parts(screeps::Part::Carry) * 10,
+                distance: dist / moves,
+                store: (dist - used).max(0) / moves - dist / moves,
+                ..CostParts::default()
+            })
+        }
+        JobType::Upgrade if c.has_parts_for_job(JobType::Upgrade) && energy > 0 => {
+            cost(100 - parts(screeps::Part::Work) * 5, dist)
+        }
+        JobType::Pickup if c.has_parts_for_job(JobType::Transfer) && energy < 10 => cost(
+            100 - 5 * (parts(screeps::Part::Carry) + parts(screeps::Part::Move)),
+            dist / moves,
+        ),
+        JobType::Build if c.has_parts_for_job(JobType::Build) && energy > 0 => {
+            cost(100 - 5 * parts(screeps::Part::Work), dist / moves)
+        }
+        JobType::Repair if c.has_parts_for_job(JobType::Repair) && energy > 0 => {
+            cost(100 - 5 * parts(screeps::Part::Work), dist / moves)
+        }
+        JobType::Withdraw
+            if c.has_parts_for_job(JobType::Withdraw) && c.store_free_capacity(None) > 0 =>
+        {
+            cost(100 - 5 * parts(screeps::Part::Carry), dist)
+        }
+        JobType::Claim | JobType::Scout if c.has_parts_for_job(JobType::Claim) => {
+            cost(10 - parts(screeps::Part::Move), 0)
+        }
+        JobType::Renew if lifecycle::wants_renew(c) => cost(0, dist / moves),
+        JobType::Recycle if lifecycle::obsolete(c) => cost(0, dist / moves),
+        // not matched here; a commitment to one of these is simply dropped
+        _ => None,
+    }
+}
+
+/// Full cost of moving `c` onto `ctx`, priority class and switching included.
+fn pair_cost(c: &Creep, ctx: &Context, current: Option<&Context>) -> Option<CostParts> {
+    job_cost(c, ctx).map(|p| CostParts {
+        priority: ctx.class.cost_offset() as i32,
+        switch: switch_cost(c, current, ctx) as i32,
+        ..p
+    })
+}
+
+/// Matches the `members` creeps against the `jobs` contexts (both indices into
+/// the full lists), skipping pairs `allowed` rejects. Returns (creep, job, cost)
+/// with the cost the solver went by.
+fn solve(
+    creeps: &[Creep],
+    contexts: &[Context],
+    commitments: &[Option<Context>],
+    members: &[usize],
+    jobs: &[usize],
+    ledger: &Ledger,
+    allowed: impl Fn(usize, usize) -> bool,
+) -> Vec<(usize, usize, CostParts)> {
+    if members.is_empty() || jobs.is_empty() {
+        return vec![];
+    }
+    let sub_creeps = members
+        .iter()
+        .map(|&i| creeps[i].clone())
+        .collect::<Vec<Creep>>();
+    let sub_jobs = jobs.iter().map(|&j| contexts[j]).collect::<Vec<Context>>();
+
+    let parts = members
+        .iter()
+        .flat_map(|&i| {
+            let c = &creeps[i];
+            let current = commitments[i];
+            let allowed = &allowed;
+            jobs.iter().map(move |&j| match allowed(i, j) {
+                true => pair_cost(c, &contexts[j], current.as_ref()),
+                false => None,
+            })
+        })
+        .collect::<Vec<Option<CostParts>>>();
+    let costs = parts
+        .iter()
+        .map(|p| p.map(|p| p.total()))
+        .collect::<Vec<Option<u32>>>();
+
+    members.iter().enumerate().for_each(|(m, &i)| {
+        let candidates = jobs
+            .iter()
+            .enumerate()
+            .filter_map(|(sj, &j)| {
+                parts[m * jobs.len() + sj].map(|cost| audit::Candidate {
+                    job: contexts[j].job,
+                    target: contexts[j].target,
+                    class: contexts[j].class,
+                    cost,
+                })
+            })
+            .collect();
+        audit::consider(&creeps[i].name(), candidates);
+    });
+
+    assign(&sub_creeps, &sub_jobs, &costs, ledger)
+        .iter()
+        .enumerate()
+        .filter_map(|(m, a)| {
+            a.and_then(|sj| parts[m * jobs.len() + sj].map(|cost| (members[m], jobs[sj], cost)))
+        })
+        .collect()
+}
+
+/// A creep is only ever given one job, so a solve can overshoot a target
+/// slightly; hand out what's left of each cheapest-first and drop the rest.
+fn grant(
+    creeps: &[Creep],
+    contexts: &[Context],
+    ledger: &mut Ledger,
+    mut picks: Vec<(usize, usize, CostParts)>,
+) -> Vec<(usize, usize, CostParts)> {
+    picks.sort_by_key(|(_, _, cost)| cost.total());
+    picks
+        .into_iter()
+        .filter(|&(i, j, _)| {
+            let c = &creeps[i];
+            let ctx = &contexts[j];
+            match ctx.target_id {
+                Some(id) => match ledger.remaining(&id, ctx.demand, &c.name()) {
+                    0 => false,
+                    available => {
+                        ledger.reserve(id, &c.name(), offer(c, ctx.job).min(available));
+                        true
+                    }
+                },
+                None => true,
+            }
+        })
+        .collect()
+}
+
+/// Gives each creep at most one job at the least total cost. Every creep is
+/// one unit of flow; a job takes as many creeps as its remaining demand needs
+/// at what its candidates would each commit, so costs (and with them the
+/// priority classes) decide who goes where. Jobs get fewer creeps than they
+/// want when there aren't enough to go round.
+fn assign(
+    creeps: &[Creep],
+    contexts: &[Context],
+    costs: &[Option<u32>],
+    ledger: &Ledger,
+) -> Vec<Option<usize>> {
+    let width = contexts.len();
+    let slots = contexts
+        .iter()
+        .enumerate()
+        .map(|(j, ctx)| {
+            let remaining = match ctx.target_id {
+                Some(id) => ctx.demand.saturating_sub(ledger.committed(&id, None)),
+                None => ctx.demand,
+            };
+            let offers = creeps
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| costs[i * width + j].is_some())
+                .map(|(_, c)| offer(c, ctx.job).max(1))
+                .collect::<Vec<u32>>();
+            match offers.len() {
+                0 => 0,
+                n => {
+                    let mean = (offers.iter().sum::<u32>() / n as u32).max(1);
+                    (remaining + mean - 1) / mean
+                }
+            }
+        })
+        .collect::<Vec<u32>>();
+    mcmf::assign(costs, &slots)
+}
+
+pub fn prioritize(mut creeps: Vec<screeps::Creep>) {
     let defenders = filters::get_my_rooms()
         .iter()
         .flat_map(|room| get_defense_jobs(creeps.to_vec(), room))
@@ -624,6 +1047,13 @@ pub fn prioritize(mut creeps: Vec<screeps::Creep>) {
 
     creeps.retain(|c| !defenders.contains(c));
 
+    // civilians near hostiles get out of reach before taking any job
+    let fleeing = creeps
+        .iter()
+        .filter(|c| flee::retreat(c))
+        .cloned()
+        .collect::<Vec<Creep>>();
+
     // let mut harvesters = assign_harvesters(creeps.to_vec());
     // creeps.retain(|c| !harvesters.contains(c));
 
@@ -636,323 +1066,419 @@ pub fn prioritize(mut creeps: Vec<screeps::Creep>) {
     contexts.extend(get_build_jobs(creeps.to_vec()));
     contexts.extend(get_repair_jobs(creeps.to_vec()));
     contexts.extend(get_scout_jobs(creeps.to_vec()));
-    // contexts.extend(get_withdraw_j?obs(creeps.to_vec()));
+    contexts.extend(get_lifecycle_jobs(creeps.to_vec()));
 
-    let height = creeps.len();
-    let width = contexts.len();
+    // rooms that lost their economy only harvest and refill spawns
+    for room in filters::get_my_rooms().iter() {
+        recovery::update(room, &colony::members(room.name(), &creeps));
+    }
+    let recovering = recovery::rooms();
+    contexts.retain(|ctx| {
+        !recovering.contains(&ctx.target.room_name()) || recovery::allows(ctx.job, &ctx.target)
+    });
 
-    let mut harvesters = vec![];
-    let mut upgraders = vec![];
-    let mut haulers = vec![];
-    let mut builders = vec![];
-    let mut repairers = vec![];
-    let mut gatherers = vec![];
+    // fleeing creeps and replacements on their way to take over still count
+    // for demand and spawning, they just take no job
+    let civilians = creeps.to_vec();
+    creeps.retain(|c| !fleeing.contains(c) && !succession::en_route(c));
+    // contexts.extend(get_withdraw_j?obs(creeps.to_vec()));
 
-    let matrix = creeps
+    let mut ledger = ledger::take();
+    audit::begin();
+
+    let commitments = creeps
         .iter()
         .map(|c| {
-            contexts.iter().map(move |ctx| match ctx.job {
-                JobType::Harvest if c.has_parts_for_job(JobType::Harvest) => {
-                    100_u32
-                        .checked_sub(c.get_active_bodyparts(screeps::Part::Work) * 5)
-                        .unwrap_or(0)
-                        + c.get_active_bodyparts(screeps::Part::Move)
-                        + ctx.target.get_range_to(c) * 3
-                }
-                JobType::Harvest => u32::MAX,
-                JobType::Transfer
-                    if c.has_parts_for_job(JobType::Transfer)
-                        && c.store_used_capacity(None) > 0 =>
-                {
-                    (100 - (c.get_active_bodyparts(screeps::Part::Carry) * 10))
-                        + (ctx
-                            .target
-                            .get_range_to(c)
-                            .checked_sub(c.store_used_capacity(None))
-                            .unwrap_or(0)
-                            / c.get_active_bodyparts(screeps::Part::Move))
-                }
-                JobType::Transfer => u32::MAX,
-                JobType::Upgrade
-                    if c.has_parts_for_job(JobType::Upgrade)
-                        && c.store_used_capacity(Some(ResourceType::Energy)) > 0 =>
-                {
-                    (100 - (c.get_active_bodyparts(screeps::Part::Work) as u32 * 5))
-                        + ctx.target.get_range_to(c)
-                }
-                JobType::Upgrade => u32::MAX,
-                JobType::Pickup
-                    if c.has_parts_for_job(JobType::Transfer)
-                        && c.store_used_capacity(Some(ResourceType::Energy)) < 10 =>
-                {
-                    (100 - 5
-                        * (c.get_active_bodyparts(screeps::Part::Carry)
-                            + c.get_active_bodyparts(screeps::Part::Move) as u32))
-                        + (ctx.target.get_range_to(c) / c.get_active_bodyparts(screeps::Part::Move))
-                }
-                JobType::Pickup => u32::MAX,
-                JobType::Build
-                    if c.has_parts_for_job(JobType::Build)
-                        && c.store_used_capacity(Some(ResourceType::Energy)) > 0 =>
-                {
-                    (100 - (5 * c.get_active_bodyparts(screeps::Part::Work) as u32))
-                        + (ctx.target.get_range_to(c) / c.get_active_bodyparts(screeps::Part::Move))
-                }
-                JobType::Build => u32::MAX,
-                JobType::Repair
-                    if c.has_parts_for_job(JobType::Repair)
-                        && c.store_used_capacity(Some(ResourceType::Energy)) > 0 =>
-                {
-                    (100 - (5 * c.get_active_bodyparts(screeps::Part::Work) as u32))
-                        + (ctx.target.get_range_to(c) / c.get_active_bodyparts(screeps::Part::Move))
-                }
-                JobType::Repair => u32::MAX,
-                JobType::Station => todo!(),
-                JobType::Withdraw
-                    if c.has_parts_for_job(JobType::Withdraw)
-                        && c.store_free_capacity(None) > 0 =>
-                {
-                    (100 - (5 * c.get_active_bodyparts(screeps::Part::Carry) as u32))
-                        + ctx.target.get_range_to(c)
-                }
-                JobType::Withdraw => u32::MAX,
-                JobType::Claim if c.has_parts_for_job(JobType::Claim) => {
-                    10 - c.get_active_bodyparts(screeps::Part::Move) as u32
-                }
-                JobType::Claim => u32::MAX,
-                JobType::Reserve => todo!(),
-                JobType::Attack => todo!(),
-                JobType::AttackR => todo!(),
-                JobType::Defend => todo!(),
-                JobType::DefendR => todo!(),
-                JobType::Heal => todo!(),
-                JobType::Scout if c.has_parts_for_job(JobType::Claim) => {
-                    10 - c.get_active_bodyparts(screeps::Part::Move) as u32
-                }
-                JobType::Scout => u32::MAX,
+            read_commitment(c).filter(|cur| {
+                contexts.iter().any(|ctx| ctx.same_job(cur)) && job_cost(c, cur).is_some()
             })
         })
-        .flatten()
-        .collect::<Vec<u32>>();
+        .collect::<Vec<Option<Context>>>();
 
-    let assignments = hungarian::minimize(&matrix, height, width);
+    // Between full solves only creeps without a live job (finished, failed,
+    // preempted or new) are matched; the rest keep theirs and its claim.
+    let full = screeps::game::time() % FULL_SOLVE_INTERVAL == 0;
+    let kept = creeps
+        .iter()
+        .zip(commitments.iter())
+        .map(|(c, cur)| match cur {
+            Some(cur) if !full && !preempted(c, cur, &contexts, &ledger) => {
+                contexts.iter().position(|ctx| ctx.same_job(cur))
+            }
+            _ => None,
+        })
+        .collect::<Vec<Option<usize>>>();
+    let free = (0..creeps.len())
+        .filter(|&i| kept[i].is_none())
+        .collect::<Vec<usize>>();
+    free.iter()
+        .for_each(|&i| ledger.release_creep(&creeps[i].name()));
+
+    // kept creeps refresh their claims first so new matches can't crowd them out
+    let mut granted = grant(
+        &creeps,
+        &contexts,
+        &mut ledger,
+        kept.iter()
+            .enumerate()
+            .filter_map(|(i, k)| {
+                k.map(|j| {
+                    let cost = pair_cost(&creeps[i], &contexts[j], commitments[i].as_ref());
+                    (i, j, cost.unwrap_or_default())
+                })
+            })
+            .collect(),
+    );
 
-    assignments
+    let homes = colony::homes();
+    let creep_homes = creeps
         .iter()
-        .filter_map(|&a| a)
-        .enumerate()
-        .for_each(|(i, j)| {
-            let h = &creeps[i];
-            let s = &contexts[j];
-            info!("{:?} assigned to {:?}", h.name(), s,);
-            if h.has_parts_for_job(s.job) {
-                match s.job {
-                    JobType::Harvest => {
-                        if let Some(source) = s.target.find_closest_by_range(find::SOURCES) {
-                            match h.harvest(&source) {
-                                screeps::ReturnCode::Ok => {
-                                    harvesters.push(h.to_owned());
-                                }
-                                screeps::ReturnCode::NotInRange => {
-                                    h.move_to(&source);
-                                }
-                                _ => {}
+        .map(|c| colony::creep_home(c, &homes))
+        .collect::<Vec<Option<RoomName>>>();
+    let job_homes = contexts
+        .iter()
+        .map(|ctx| colony::home_of(ctx.target.room_name(), &homes))
+        .collect::<Vec<Option<RoomName>>>();
+
+    let label = if full {
+        "assign_full"
+    } else {
+        "assign_incremental"
+    };
+    profiler::measure(label, || {
+        // each colony matches its own creeps to its own jobs
+        let mut partitions: HashMap<Option<RoomName>, (Vec<usize>, Vec<usize>)> = HashMap::new();
+        free.iter()
+            .for_each(|&i| partitions.entry(creep_homes[i]).or_default().0.push(i));
+        (0..contexts.len()).for_each(|j| partitions.entry(job_homes[j]).or_default().1.push(j));
+
+        let picks = partitions
+            .values()
+            .flat_map(|(members, jobs)| {
+                solve(
+                    &creeps,
+                    &contexts,
+                    &commitments,
+                    members,
+                    jobs,
+                    &ledger,
+                    |_, _| true,
+                )
+            })
+            .collect::<Vec<(usize, usize, CostParts)>>();
+        granted.extend(grant(&creeps, &contexts, &mut ledger, picks));
+
+        // then creeps left idle are lent to nearby colonies' urgent jobs
+        let idle = free
+            .iter()
+            .copied()
+            .filter(|i| granted.iter().all(|(gi, _, _)| gi != i))
+            .collect::<Vec<usize>>();
+        let urgent = (0..contexts.len())
+            .filter(|&j| contexts[j].class <= LEND_MAX_CLASS)
+            .collect::<Vec<usize>>();
+        if idle.is_empty() || urgent.is_empty() {
+            return;
+        }
+        let lendable = |i: usize, j: usize| match (creep_homes[i], job_homes[j]) {
+            (Some(a), Some(b)) => a != b && colony::distance(a, b) <= LEND_RANGE,
+            _ => false,
+        };
+        let loans = solve(
+            &creeps,
+            &contexts,
+            &commitments,
+            &idle,
+            &urgent,
+            &ledger,
+            lendable,
+        );
+        loans.iter().for_each(|&(i, j, _)| {
+            info!(
+                "{:?} lent to {:?} for {:?}",
+                creeps[i].name(),
+                job_homes[j],
+                contexts[j].job
+            )
+        });
+        granted.extend(grant(&creeps, &contexts, &mut ledger, loans));
+    });
+    // total cost of the whole assignment, so both modes compare like for like
+    profiler::score(
+        label,
+        granted.iter().map(|(_, _, cost)| cost.total() as f64).sum(),
+    );
+
+    creeps.iter().enumerate().for_each(|(i, c)| {
+        let grant = granted.iter().find(|(gi, _, _)| *gi == i);
+        let ctx = grant.map(|(_, j, _)| &contexts[*j]);
+        // a creep that moved on gives up its claim on the old target
+        if let Some(old) = commitments[i].or_else(|| read_commitment(c)) {
+            if let Some(old_id) = old.target_id {
+                if ctx.and_then(|ctx| ctx.target_id) != Some(old_id) {
+                    ledger.release(old_id, &c.name());
+                }
+            }
+        }
+        write_commitment(c, ctx);
+
+        let chosen = grant.map(|&(_, j, cost)| audit::Candidate {
+            job: contexts[j].job,
+            target: contexts[j].target,
+            class: contexts[j].class,
+            cost,
+        });
+        audit::decide(&c.name(), chosen, kept[i].is_some());
+    });
+    ledger::put(ledger);
+
+    if screeps::memory::root().bool("audit_visual") {
+        audit::draw();
+    }
+
+    granted.iter().for_each(|&(i, j, _)| {
+        let h = &creeps[i];
+        let s = &contexts[j];
+        info!("{:?} assigned to {:?}", h.name(), s,);
+        let class = s.class;
+        if h.has_parts_for_job(s.job) {
+            match s.job {
+                JobType::Harvest => {
+                    if let Some(source) = s.target.find_closest_by_range(find::SOURCES) {
+                        match h.harvest(&source) {
+                            screeps::ReturnCode::Ok => {
+                                traffic::park(h, &source.pos(), 1, class);
                             }
-                        }
-                    }
-                    JobType::Upgrade => {
-                        if let Some(st) = s.target.find_closest_by_range(find::MY_STRUCTURES) {
-                            match st.as_structure() {
-                                screeps::Structure::Controller(ctrl) => {
-                                    match h.upgrade_controller(&ctrl) {
-                                        screeps::ReturnCode::Ok => {
-                                            upgraders.push(h.to_owned());
-                                        }
-                                        screeps::ReturnCode::NotInRange => {
-                                            h.move_to(&ctrl);
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                                _ => {}
+                            screeps::ReturnCode::NotInRange => {
+                                traffic::move_to(h, &source.pos(), 1, class);
                             }
+                            _ => {}
                         }
                     }
-                    JobType::Transfer => {
-                        if let Some(st) = s.target.find_closest_by_range(find::STRUCTURES) {
-                            match st.as_transferable() {
-                                Some(s) => match h.transfer_all(s, ResourceType::Energy) {
+                }
+                JobType::Upgrade => {
+                    if let Some(st) = s.target.find_closest_by_range(find::MY_STRUCTURES) {
+                        match st.as_structure() {
+                            screeps::Structure::Controller(ctrl) => {
+                                match h.upgrade_controller(&ctrl) {
                                     screeps::ReturnCode::Ok => {
-                                        haulers.push(h.to_owned());
-                                    }
-                                    screeps::ReturnCode::NotEnough => {
-                                        h.transfer_amount(
-                                            s,
-                                            ResourceType::Energy,
-                                            h.store_capacity(Some(ResourceType::Energy)),
-                                        );
-                                    }
-                                    screeps::ReturnCode::Full => {
-                                        h.drop(
-                                            ResourceType::Energy,
-                                            Some(h.store_used_capacity(Some(ResourceType::Energy))),
-                                        );
+                                        traffic::park(h, &ctrl.pos(), 3, class);
                                     }
                                     screeps::ReturnCode::NotInRange => {
-                                        h.move_to(&s.pos());
+                                        traffic::move_to(h, &ctrl.pos(), 3, class);
                                     }
                                     _ => {}
-                                },
-                                None => {}
+                                }
                             }
+                            _ => {}
                         }
                     }
-                    JobType::Pickup => {
-                        if let Some(r) = s.target.find_closest_by_range(find::DROPPED_RESOURCES) {
-                            match h.pickup(&r) {
-                                screeps::ReturnCode::Ok => {
-                                    gatherers.push(h.to_owned());
+                }
+                JobType::Transfer => {
+                    if let Some(st) = s.target.find_closest_by_range(find::STRUCTURES) {
+                        match st.as_transferable() {
+                            Some(s) => match h.transfer_all(s, ResourceType::Energy) {
+                                screeps::ReturnCode::Ok => {}
+                                screeps::ReturnCode::NotEnough => {
+                                    h.transfer_amount(
+                                        s,
+                                        ResourceType::Energy,
+                                        h.store_capacity(Some(ResourceType::Energy)),
+                                    );
+                                }
+                                screeps::ReturnCode::Full => {
+                                    h.drop(
+                                        ResourceType::Energy,
+                                        Some(h.store_used_capacity(Some(ResourceType::Energy))),
+                                    );
                                 }
                                 screeps::ReturnCode::NotInRange => {
-                                    h.move_to(&r.pos());
+                                    traffic::move_to(h, &s.pos(), 1, class);
                                 }
                                 _ => {}
-                            }
+                            },
+                            None => {}
                         }
                     }
-                    JobType::Build => {
-                        if let Some(st) = s.target.find_closest_by_range(find::CONSTRUCTION_SITES) {
-                            match h.build(&st) {
-                                screeps::ReturnCode::Ok => {
-                                    builders.push(h.to_owned());
-                                }
-                                screeps::ReturnCode::NotInRange => {
-                                    h.move_to(&s.target);
-                                }
-                                _ => {
-                                    info!("Error building");
-                                }
+                }
+                JobType::Pickup => {
+                    if let Some(r) = s.target.find_closest_by_range(find::DROPPED_RESOURCES) {
+                        match h.pickup(&r) {
+                            screeps::ReturnCode::Ok => {}
+                            screeps::ReturnCode::NotInRange => {
+                                traffic::move_to(h, &r.pos(), 1, class);
                             }
+                            _ => {}
                         }
                     }
-                    JobType::Repair => {
-                        if let Some(st) = s.target.find_closest_by_range(find::STRUCTURES) {
-                            match h.repair(&st) {
-                                screeps::ReturnCode::Ok => {
-                                    repairers.push(h.to_owned());
-                                }
-                                screeps::ReturnCode::NotInRange => {
-                                    h.move_to(&s.target);
-                                }
-                                _ => {}
+                }
+                JobType::Build => {
+                    if let Some(st) = s.target.find_closest_by_range(find::CONSTRUCTION_SITES) {
+                        match h.build(&st) {
+                            screeps::ReturnCode::Ok => {
+                                traffic::park(h, &st.pos(), 3, class);
+                            }
+                            screeps::ReturnCode::NotInRange => {
+                                traffic::move_to(h, &s.target, 3, class);
+                            }
+                            _ => {
+                                info!("Error building");
                             }
                         }
                     }
-                    JobType::Withdraw => {
-                        if let Some(st) = s.target.find_closest_by_range(find::STRUCTURES) {
-                            if vec![
-                                screeps::StructureType::Storage,
-                                screeps::StructureType::Container,
-                            ]
-                            .contains(&st.structure_type())
-                            {
-                                match st.as_withdrawable() {
-                                    Some(wd) => match h.withdraw_amount(
-                                        wd,
-                                        ResourceType::Energy,
-                                        h.store_free_capacity(None) as u32,
-                                    ) {
-                                        screeps::ReturnCode::Ok => {
-                                            haulers.push(h.to_owned());
-                                        }
-                                        screeps::ReturnCode::NotEnough => {
-                                            h.withdraw_all(wd, ResourceType::Energy);
-                                        }
-                                        screeps::ReturnCode::NotInRange => {
-                                            h.move_to(&s.target);
-                                        }
-                                        _ => {}
-                                    },
-                                    None => {}
-                                }
+                }
+                JobType::Repair => {
+                    if let Some(st) = s.target.find_closest_by_range(find::STRUCTURES) {
+                        match h.repair(&st) {
+                            screeps::ReturnCode::Ok => {
+                                traffic::park(h, &st.pos(), 3, class);
                             }
+                            screeps::ReturnCode::NotInRange => {
+                                traffic::move_to(h, &s.target, 3, class);
+                            }
+                            _ => {}
                         }
                     }
-                    JobType::Station => {
-                        h.move_to(&s.target);
-                    }
-                    JobType::Scout => todo!(),
-                    JobType::Reserve => todo!(),
-                    JobType::Attack => todo!(),
-                    JobType::AttackR => todo!(),
-                    JobType::Defend => todo!(),
-                    JobType::DefendR => todo!(),
-                    JobType::Heal => todo!(),
-                    JobType::Claim => {
-                        if h.room().unwrap().name() != s.target.room_name() {
-                            h.move_to(&s.target);
-                        } else {
-                            let l = s.target.look();
-                            l.iter().for_each(|lr| match lr {
-                                screeps::LookResult::Source(s) => match h.harvest(s) {
+                }
+                JobType::Withdraw => {
+                    if let Some(st) = s.target.find_closest_by_range(find::STRUCTURES) {
+                        if vec![
+                            screeps::StructureType::Storage,
+                            screeps::StructureType::Container,
+                        ]
+                        .contains(&st.structure_type())
+                        {
+                            match st.as_withdrawable() {
+                                Some(wd) => match h.withdraw_amount(
+                                    wd,
+                                    ResourceType::Energy,
+                                    h.store_free_capacity(None) as u32,
+                                ) {
                                     screeps::ReturnCode::Ok => {}
-                                    screeps::ReturnCode::NotInRange => {
-                                        h.move_to(s);
+                                    screeps::ReturnCode::NotEnough => {
+                                        h.withdraw_all(wd, ResourceType::Energy);
                                     }
-                                    _ => {
-                                        info!("Error scout-harvesting");
+                                    screeps::ReturnCode::NotInRange => {
+                                        traffic::move_to(h, &s.target, 1, class);
                                     }
+                                    _ => {}
                                 },
-                                screeps::LookResult::Structure(st) => match st.room() {
-                                    Some(rm) => match rm.controller() {
-                                        Some(ctrl) => match h.claim_controller(&ctrl) {
-                                            screeps::ReturnCode::Ok => {}
-                                            screeps::ReturnCode::NotInRange => {
-                                                h.move_to(&ctrl);
-                                            }
-                                            screeps::ReturnCode::GclNotEnough => {
-                                                match h.reserve_controller(&ctrl) {
-                                                    screeps::ReturnCode::Ok => {}
-                                                    screeps::ReturnCode::NotInRange => {
-                                                        h.move_to(&ctrl);
-                                                    }
-                                                    _ => {
-                                                        info!("Error scouting");
-                                                    }
+                                None => {}
+                            }
+                        }
+                    }
+                }
+                JobType::Station => {
+                    traffic::move_to(h, &s.target, 0, class);
+                }
+                JobType::Renew => {
+                    if let Some(spawn) = s.target.find_closest_by_range(find::MY_SPAWNS) {
+                        match spawn.renew_creep(h) {
+                            screeps::ReturnCode::NotInRange => {
+                                traffic::move_to(h, &spawn.pos(), 1, class);
+                            }
+                            screeps::ReturnCode::Ok | screeps::ReturnCode::Full => {}
+                            r => info!("{:?} couldn't be renewed: {:?}", h.name(), r),
+                        }
+                    }
+                }
+                JobType::Recycle => {
+                    if let Some(spawn) = s.target.find_closest_by_range(find::MY_SPAWNS) {
+                        match spawn.recycle_creep(h) {
+                            screeps::ReturnCode::NotInRange => {
+                                traffic::move_to(h, &spawn.pos(), 1, class);
+                            }
+                            screeps::ReturnCode::Ok => {
+                                info!("{:?} recycled", h.name());
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                JobType::Scout => {
+                    traffic::move_to(h, &s.target, 1, class);
+                }
+                JobType::Dismantle => {
+                    let target = s
+                        .target
+                        .look_for(look::STRUCTURES)
+                        .into_iter()
+                        .find(|st| st.structure_type() != screeps::StructureType::Road);
+                    if let Some(st) = target {
+                        match h.dismantle(&st) {
+                            screeps::ReturnCode::NotInRange => {
+                                traffic::move_to(h, &s.target, 1, class);
+                            }
+                            screeps::ReturnCode::Ok => {}
+                            r => info!("{:?} couldn't dismantle: {:?}", h.name(), r),
+                        }
+                    }
+                }
+                // never assigned here; stay put rather than act on a stale commitment
+                JobType::Reserve
+                | JobType::Attack
+                | JobType::AttackR
+                | JobType::Defend
+                | JobType::DefendR
+                | JobType::Heal => {
+                    warn!("{:?} has no executor for {:?}", h.name(), s.job);
+                    traffic::park(h, &h.pos(), 1, class);
+                }
+                JobType::Claim => {
+                    if h.room().unwrap().name() != s.target.room_name() {
+                        traffic::move_to(h, &s.target, 1, class);
+                    } else {
+                        let l = s.target.look();
+                        l.iter().for_each(|lr| match lr {
+                            screeps::LookResult::Source(s) => match h.harvest(s) {
+                                screeps::ReturnCode::Ok => {}
+                                screeps::ReturnCode::NotInRange => {
+                                    traffic::move_to(h, &s.pos(), 1, class);
+                                }
+                                _ => {
+                                    info!("Error scout-harvesting");
+                                }
+                            },
+                            screeps::LookResult::Structure(st) => match st.room() {
+                                Some(rm) => match rm.controller() {
+                                    Some(ctrl) => match h.claim_controller(&ctrl) {
+                                        screeps::ReturnCode::Ok => {}
+                                        screeps::ReturnCode::NotInRange => {
+                                            traffic::move_to(h, &ctrl.pos(), 1, class);
+                                        }
+                                        screeps::ReturnCode::GclNotEnough => {
+                                            match h.reserve_controller(&ctrl) {
+                                                screeps::ReturnCode::Ok => {}
+                                                screeps::ReturnCode::NotInRange => {
+                                                    traffic::move_to(h, &ctrl.pos(), 1, class);
+                                                }
+                                                _ => {
+                                                    info!("Error scouting");
                                                 }
                                             }
-                                            _ => {
-                                                info!("Error scouting");
-                                            }
-                                        },
-                                        None => {
-                                            h.move_to(&s.target);
+                                        }
+                                        _ => {
+                                            info!("Error scouting");
                                         }
                                     },
                                     None => {
-                                        h.move_to(&s.target);
+                                        traffic::move_to(h, &s.target, 1, class);
                                     }
                                 },
-                                _ => {}
-                            })
-                        }
+                                None => {
+                                    traffic::move_to(h, &s.target, 1, class);
+                                }
+                            },
+                            _ => {}
+                        })
                     }
                 }
             }
-        });
-
-    if (width > height || total < 6) && total < 30 {
-        if screeps::game::time() % 15 == 1 {
-            spawning::init(
-                creeps.to_vec(),
-                harvesters.to_vec(),
-                haulers.to_vec(),
-                builders.to_vec(),
-                repairers.to_vec(),
-                upgraders.to_vec(),
-                gatherers.to_vec(),
-                defenders.to_vec(),
-            );
         }
+    });
+
+    // the demand model decides whether anything is missing
+    if screeps::game::time() % 15 == 1 {
+        spawning::init(&civilians, &defenders);
     }
 }
@@ -0,0 +1,173 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use log::{debug, warn};
+use screeps::RoomName;
+
+use crate::world::{self, RoomDescription};
+
+/// Routes are planned again after this long, in case rooms changed hands.
+const ROUTE_TTL: u32 = 1000;
+
+/// Hostiles seen in a room more recently than this make it costlier.
+const HOSTILES_TTL: u32 = 1500;
+
+const OWN_ROOM_COST: f64 = 1.0;
+const HIGHWAY_COST: f64 = 1.0;
+const VACANT_COST: f64 = 2.0;
+/// Rooms we never saw.
+const UNKNOWN_COST: f64 = 2.5;
+const RESERVED_COST: f64 = 4.0;
+const KEEPER_COST: f64 = 10.0;
+/// Owned by someone else but without towers.
+const UNDEFENDED_COST: f64 = 8.0;
+const HOSTILES_COST: f64 = 5.0;
+
+/// Room sequences between pairs of rooms, kept in `Memory.routes`.
+#[derive(Debug, Default)]
+struct Routes {
+    loaded: bool,
+    dirty: bool,
+    /// "<from>:<to>" -> (rooms from `from` to `to` inclusive, tick planned)
+    routes: HashMap<String, (Vec<String>, u32)>,
+}
+
+thread_local! {
+    static ROUTES: RefCell<Routes> = RefCell::new(Routes::default());
+}
+
+fn key(from: RoomName, to: RoomName) -> String {
+    format!("{}:{}", from, to)
+}
+
+/// What passing through `room` costs, from what we know of it. Rooms owned
+/// by someone with towers can't be passed at all.
+fn room_cost(room: RoomName) -> f64 {
+    let time = screeps::game::time();
+    let intel = world::intel(room);
+    let kind = intel
+        .as_ref()
+        .map(|i| i.kind)
+        .or_else(|| world::describe_name(room));
+    let base = match kind {
+        Some(RoomDescription::My) | Some(RoomDescription::MyReserved) => OWN_ROOM_COST,
+        Some(RoomDescription::Highway) => HIGHWAY_COST,
+        Some(RoomDescription::Vacant) | Some(RoomDescription::Center) => VACANT_COST,
+        Some(RoomDescription::HostileReserved) => RESERVED_COST,
+        Some(RoomDescription::SourceKeeper) => KEEPER_COST,
+        Some(RoomDescription::Hostile) => match intel.as_ref().map(|i| i.towers) {
+            Some(0) => UNDEFENDED_COST,
+            _ => f64::INFINITY,
+        },
+        None => UNKNOWN_COST,
+    };
+    match intel {
+        Some(i) if i.hostiles > 0 && time.saturating_sub(i.tick) < HOSTILES_TTL => {
+            base + HOSTILES_COST
+        }
+        _ => base,
+    }
+}
+
+impl Routes {
+    fn load(&mut self) {
+        if self.loaded {
+            return;
+        }
+        self.loaded = true;
+        if let Ok(Some(json)) = screeps::memory::root().string("routes") {
+            match serde_json::from_str::<HashMap<String, (Vec<String>, u32)>>(&json) {
+                Ok(routes) => self.routes = routes,
+                Err(e) => warn!("Deserialization error: {:?}", e),
+            }
+        }
+    }
+
+    fn save(&mut self) {
+        let time = screeps::game::time();
+        let before = self.routes.len();
+        self.routes
+            .retain(|_, (_, tick)| time.saturating_sub(*tick) < ROUTE_TTL);
+        if self.routes.len() != before {
+            self.dirty = true;
+        }
+        if !self.dirty {
+            return;
+        }
+        match serde_json::to_string(&self.routes) {
+            Ok(k) => screeps::memory::root().set("routes", k),
+            Err(e) => warn!("Serialization error: {:?}", e),
+        }
+        self.dirty = false;
+    }
+
+    fn route(&mut self, from: RoomName, to: RoomName) -> Option<Vec<RoomName>> {
+        self.load();
+        let time = screeps::game::time();
+        let k = key(from, to);
+        if let Some((rooms, tick)) = self.routes.get(&k) {
+            if time.saturating_sub(*tick) < ROUTE_TTL {
+                return Some(rooms.iter().filter_map(|r| RoomName::new(r).ok()).collect());
+            }
+        }
+
+        let steps = screeps::game::map::find_route_with_callback(from, to, |room, _| {
+            if room == to || room == from {
+                OWN_ROOM_COST
+            } else {
+                room_cost(room)
+            }
+        });
+        let mut rooms = vec![from];
+        match steps {
+            Ok(steps) => rooms.extend(steps.iter().map(|s| s.room)),
+            Err(e) => {
+                debug!("no route from {} to {}: {:?}", from, to, e);
+                return None;
+            }
+        }
+        self.routes
+            .insert(k, (rooms.iter().map(|r| r.to_string()).collect(), time));
+        self.dirty = true;
+        Some(rooms)
+    }
+
+    /// Drops routes through any of `rooms`.
+    fn drop_through(&mut self, rooms: &[RoomName]) {
+        self.load();
+        let names = rooms.iter().map(|r| r.to_string()).collect::<Vec<String>>();
+        let before = self.routes.len();
+        self.routes
+            .retain(|_, (route, _)| !route.iter().any(|r| names.contains(r)));
+        if self.routes.len() != before {
+            self.dirty = true;
+        }
+    }
+}
+
+/// Whether routes may pass through `room` at all.
+pub fn passable(room: RoomName) -> bool {
+    room_cost(room).is_finite()
+}
+
+/// Rooms to cross from `from` to `to`, both included, avoiding dangerous
+/// ones. None when there is no way through.
+pub fn route(from: RoomName, to: RoomName) -> Option<Vec<RoomName>> {
+    if from == to {
+        return Some(vec![from]);
+    }
+    ROUTES.with(|r| r.borrow_mut().route(from, to))
+}
+
+/// Surveys visible rooms and forgets routes through rooms that changed.
+pub fn update() {
+    let changed = world::survey();
+    if !changed.is_empty() {
+        ROUTES.with(|r| r.borrow_mut().drop_through(&changed));
+    }
+}
+
+/// Prunes old routes and writes the rest to `Memory.routes`.
+pub fn save() {
+    ROUTES.with(|r| r.borrow_mut().save())
+}
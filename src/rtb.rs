@@ -1,9 +1,10 @@
 use std::collections::HashMap;
 
 use log::warn;
-use screeps::{RoomObjectProperties, LookResult, RawObjectId, ResourceType, HasId, Attackable, HasStore, SharedCreepProperties, HasPosition};
+use screeps::{RoomObjectProperties, LookResult, RawObjectId, ResourceType, HasId, Attackable, HasStore, SharedCreepProperties, HasPosition, Position};
 
 use crate::jobs::JobType;
+use crate::pricing;
 use crate::world::RoomCustomActions;
 use serde::{Serialize,Deserialize};
 
@@ -36,6 +37,11 @@ pub enum SinkSources {
     Terminal = 25,
     Tower = 26,
     Wall = 27,
+    Ruin = 28,
+    PowerBank = 29,
+    InvaderCore = 30,
+    KeeperLair = 31,
+    Portal = 32,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
@@ -58,6 +64,59 @@ pub struct JobAsk {
     pub ty: SinkSources,
 }
 
+/// Power banks decaying sooner than this aren't worth sending attackers to.
+const POWER_BANK_MIN_TICKS: u32 = 1500;
+
+/// Haulers are staged once a power bank is below 1/n of its hits.
+const POWER_BANK_HAUL_FRACTION: u32 = 10;
+
+/// What a terminal pays per unit of power hauled home.
+const POWER_BID: u32 = 10;
+
+/// An area creeps should keep out of; fed to pathing rather than to the market.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Hazard {
+    pub pos: Position,
+    pub radius: u32,
+    pub ty: SinkSources,
+}
+
+pub trait HazardNode {
+    fn hazard(self: &Self) -> Option<Hazard>;
+}
+
+impl HazardNode for screeps::StructureKeeperLair {
+    fn hazard(self: &Self) -> Option<Hazard> {
+        // keepers sit on the lair's source; ranged attack reaches 3, plus a step of margin
+        Some(Hazard {
+            pos: self.pos(),
+            radius: 4,
+            ty: SinkSources::KeeperLair
+        })
+    }
+}
+impl HazardNode for screeps::StructurePortal {
+    fn hazard(self: &Self) -> Option<Hazard> {
+        // stepping on a portal moves the creep; never path across one by accident
+        Some(Hazard {
+            pos: self.pos(),
+            radius: 0,
+            ty: SinkSources::Portal
+        })
+    }
+}
+
+pub fn load_hazards(room: &screeps::Room) -> Vec<Hazard> {
+    room.find(screeps::find::STRUCTURES)
+        .iter()
+        .filter_map(|st| match st {
+            screeps::Structure::KeeperLair(st) => st.hazard(),
+            screeps::Structure::Portal(st) => st.hazard(),
+            _ => None,
+        })
+        .collect()
+}
+
 pub trait SinkNode {
     fn bid(self: &Self) -> u32;
     fn sink_request(self: &Self) -> Option<JobBid>;
@@ -224,7 +283,7 @@ impl SinkNode for screeps::StructureController {
 }
 
 impl SinkNode for screeps::StructureLink {
-    fn bid(self: &Self) -> u32 { 20 }  // TODO
+    fn bid(self: &Self) -> u32 { pricing::quote(self.untyped_id(), SinkSources::Link) }
 
     fn sink_request(self: &Self) -> Option<JobBid> {
         if self.hits() < self.hits_max() - 100 {
@@ -285,7 +344,7 @@ impl SinkNode for screeps::StructureLab {
 }
 
 impl SinkNode for screeps::StructureStorage {
-    fn bid(self: &Self) -> u32 { 5 } // TODO
+    fn bid(self: &Self) -> u32 { pricing::quote(self.untyped_id(), SinkSources::Storage) }
     fn sink_request(self: &Self) -> Option<JobBid> {
         if self.hits() < self.hits_max() - 100 {
             Some(JobBid {
@@ -309,7 +368,7 @@ impl SinkNode for screeps::StructureStorage {
     }
 }
 impl SinkNode for screeps::StructureTower {
-    fn bid(self: &Self) -> u32 { 3 } // TODO
+    fn bid(self: &Self) -> u32 { pricing::quote(self.untyped_id(), SinkSources::Tower) }
     fn sink_request(self: &Self) -> Option<JobBid> {
         if self.hits() < self.hits_max() - 100 {
             Some(JobBid {
@@ -333,7 +392,7 @@ impl SinkNode for screeps::StructureTower {
     }
 }
 impl SinkNode for screeps::StructurePowerSpawn {
-    fn bid(self: &Self) -> u32 { 10 } // TODO
+    fn bid(self: &Self) -> u32 { pricing::quote(self.untyped_id(), SinkSources::PowerSpawn) }
     fn sink_request(self: &Self) -> Option<JobBid> {
 
         if self.hits() < self.hits_max() - 100 {
@@ -360,7 +419,7 @@ impl SinkNode for screeps::StructurePowerSpawn {
 
 impl SinkNode for screeps::StructureSpawn {
 
-    fn bid(self: &Self) -> u32 { 6 } // TODO
+    fn bid(self: &Self) -> u32 { pricing::quote(self.untyped_id(), SinkSources::Spawn) }
     fn sink_request(self: &Self) -> Option<JobBid> {
         if self.hits() < self.hits_max() - 100 {
             Some(JobBid {
@@ -444,11 +503,21 @@ impl SinkNode for screeps::StructureTerminal {
                 target: self.untyped_id(),
                 ty: SinkSources::Terminal
             })
+        } else if self.store_free_capacity(Some(ResourceType::Power)) > 0 {
+            // power hauled home from banks; without this it has no buyer
+            Some(JobBid {
+                request: JobType::Transfer,
+                resource: Some(ResourceType::Power),
+                max: self.store_free_capacity(Some(ResourceType::Power)) as u32,
+                bid: POWER_BID,
+                target: self.untyped_id(),
+                ty: SinkSources::Terminal
+            })
         } else { None }
     }
 }
 impl SinkNode for screeps::StructureContainer {
-    fn bid(self: &Self) -> u32 { 5 } // TODO
+    fn bid(self: &Self) -> u32 { pricing::quote(self.untyped_id(), SinkSources::Container) }
 
     fn sink_request(self: &Self) -> Option<JobBid> {
         if self.hits() < self.hits_max() - 100 {
@@ -561,6 +630,38 @@ impl SinkNode for screeps::ConstructionSite {
     }
 
 }
+impl SinkNode for screeps::StructurePowerBank {
+    // a bank is only worth cracking if the power outweighs the attackers' upkeep
+    fn bid(self: &Self) -> u32 { self.power() / 100 }
+
+    fn sink_request(self: &Self) -> Option<JobBid> {
+        if self.ticks_to_decay() > POWER_BANK_MIN_TICKS {
+            Some(JobBid {
+                request: JobType::Attack,
+                resource: None,
+                max: self.hits(),
+                bid: self.bid(),
+                target: self.untyped_id(),
+                ty: SinkSources::PowerBank
+            })
+        } else { None }
+    }
+}
+impl SinkNode for screeps::StructureInvaderCore {
+    fn bid(self: &Self) -> u32 { 10 * (self.level() + 1) }
+
+    fn sink_request(self: &Self) -> Option<JobBid> {
+        // a level 0 core has no defenders and can be taken apart with WORK
+        Some(JobBid {
+            request: if self.level() == 0 { JobType::Dismantle } else { JobType::Attack },
+            resource: None,
+            max: self.hits(),
+            bid: self.bid(),
+            target: self.untyped_id(),
+            ty: SinkSources::InvaderCore
+        })
+    }
+}
 // impl Sink for dyn screeps::Transferable {
 //     fn bid(self: &Self) -> u32 {
 //         todo!()
@@ -662,7 +763,7 @@ impl SourceNode for screeps::Tombstone {
 
         if biggest.is_some() {
             Some(JobAsk {
-                request: JobType::Pickup,
+                request: JobType::Withdraw,
                 resource: Some(biggest.unwrap().0),
                 max: biggest.unwrap().1,
                 ask: self.ask(),
@@ -673,6 +774,44 @@ impl SourceNode for screeps::Tombstone {
     }
 }
 
+impl SourceNode for screeps::Ruin {
+    fn ask(self: &Self) -> u32 { 1 } // sources have minimal cost
+    fn source_request(self: &Self) -> Option<JobAsk> {
+
+        let biggest = self.store_types().iter()
+        .map(|&rt| (rt, self.store_used_capacity(Some(rt))))
+        .max_by_key(|(_rt,quant)| *quant);
+
+        if biggest.is_some() {
+            Some(JobAsk {
+                request: JobType::Withdraw,
+                resource: Some(biggest.unwrap().0),
+                max: biggest.unwrap().1,
+                ask: self.ask(),
+                target: self.untyped_id(),
+                ty: SinkSources::Ruin
+            })            
+        } else { None }
+    }
+}
+impl SourceNode for screeps::StructurePowerBank {
+    fn ask(self: &Self) -> u32 { 1 } // sources have minimal cost
+    fn source_request(self: &Self) -> Option<JobAsk> {
+        // stage haulers next to the bank once it's close to breaking;
+        // the dropped power is picked up through its Resource node
+        if self.hits() < self.hits_max() / POWER_BANK_HAUL_FRACTION {
+            Some(JobAsk {
+                request: JobType::Station,
+                resource: Some(ResourceType::Power),
+                max: self.power(),
+                ask: self.ask(),
+                target: self.untyped_id(),
+                ty: SinkSources::PowerBank
+            })
+        } else { None }
+    }
+}
+
 // impl Source for dyn screeps::Withdrawable {
 //     fn ask(self: &Self) -> u32 { 1 } // sources have minimal cost
 
@@ -1,8 +1,8 @@
-use screeps::{HasPosition, LookResult, ObjectId, HasStore};
+use screeps::{HasPosition, ObjectId, HasStore};
 
-use crate::filters;
+use crate::{filters, nodes};
 use crate::jobs::JobProperties;
-use crate::rtb::{JobAsk, JobBid, SinkNode, SourceNode};
+use crate::rtb::{JobAsk, JobBid};
 
 pub fn load_asks_from_memory() -> Option<Vec<JobAsk>> {
     None
@@ -25,225 +25,11 @@ pub fn load_contexts() {
         None => vec![],
     };
 
-    filters::get_my_rooms()
-        .iter()
-        .flat_map(|room| room.look_at_area(0, 0, 50, 50))
-        .for_each(|res| {
-            match &res.look_result {
-                LookResult::Creep(st) => {
-                    match st.sink_request() {
-                        Some(req) => bid_vec.push(req),
-                        None => {}
-                    };
-                    match st.source_request() {
-                        Some(req) => ask_vec.push(req),
-                        None => {}
-                    };
-                }
-                LookResult::Energy(st) => {
-                    match st.source_request() {
-                        Some(req) => ask_vec.push(req),
-                        None => {}
-                    };
-                }
-                LookResult::Resource(st) => {
-                    match st.source_request() {
-                        Some(req) => ask_vec.push(req),
-                        None => {}
-                    };
-                }
-                LookResult::Source(st) => {
-                    match st.source_request() {
-                        Some(req) => ask_vec.push(req),
-                        None => {}
-                    };
-                }
-                LookResult::Mineral(st) => {
-                    match st.source_request() {
-                        Some(req) => ask_vec.push(req),
-                        None => {}
-                    };
-                }
-                LookResult::Deposit(st) => {
-                    match st.source_request() {
-                        Some(req) => ask_vec.push(req),
-                        None => {}
-                    };
-                }
-                LookResult::ConstructionSite(st) => {
-                    match st.sink_request() {
-                        Some(req) => bid_vec.push(req),
-                        None => {}
-                    };
-                }
-                LookResult::Tombstone(st) => {
-                    match st.source_request() {
-                        Some(req) => ask_vec.push(req),
-                        None => {}
-                    };
-                }
-                // LookResult::PowerCreep(st) => {
-                //     match st.sink_request() {
-                //         Some(req) => {bid_vec.push(req)},
-                //         None => {},
-                //     };
-                //     match st.source_request() {
-                //         Some(req) => {ask_vec.push(req)},
-                //         None => {},
-                //     };
-                // },
-                LookResult::Structure(structure) => match structure {
-                    screeps::Structure::Container(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Controller(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Extension(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Extractor(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Factory(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Lab(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Link(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Nuker(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Observer(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::PowerSpawn(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Rampart(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Road(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Spawn(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Storage(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Terminal(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Tower(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Wall(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    _ => {}
-                },
-                _ => {}
-            }
-        });
+    filters::get_my_rooms().iter().for_each(|room| {
+        let nodes = nodes::discover(room);
+        bid_vec.extend(nodes.bids());
+        ask_vec.extend(nodes.asks());
+    });
 
     creeps.sort_unstable_by_key(|c| -1 * c.body().len() as i8);
     ask_vec.sort_unstable_by_key(|job| job.ask);
@@ -1,289 +1,333 @@
-use crate::jobs::{JobProperties, JobType, SearchMove};
-use crate::{filters, flags};
-use log::info;
+use crate::jobs::{JobClass, JobProperties, JobType, SearchMove};
+use crate::spawnqueue::{self, Policy, SpawnRequest};
+use crate::travel::Mobility;
+use crate::{colony, demand, filters, meta, recovery};
 use screeps::memory::MemoryReference;
-use screeps::{
-    HasPosition, HasStore, Part, ResourceType, RoomObjectProperties, SharedCreepProperties,
-    SpawnOptions, StructureSpawn,
-};
-
-pub fn get_hostile_needs(room: &screeps::Room, defenders: &Vec<screeps::Creep>) -> Vec<Part> {
-    let mut needs = vec![];
-    needs.push(Part::Move);
-    needs.push(Part::RangedAttack);
-    return needs;
+use screeps::{HasPosition, Part, RoomObjectProperties, SpawnOptions, StructureSpawn};
+use serde::{Deserialize, Serialize};
+
+/// Most parts a creep can have.
+const MAX_PARTS: usize = 50;
+
+/// How long a defender request may sit in the spawn queue.
+const DEFENSE_DEADLINE: u32 = 100;
+
+/// How fast a body should cross terrain without building up fatigue.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Speed {
+    /// one tile per tick on roads: a MOVE per two other parts
+    Road,
+    /// one tile per tick on plains: a MOVE per other part
+    Plain,
+    /// a single MOVE, for creeps that walk once and then sit
+    Crawl,
 }
-pub fn get_needs(
-    room: &screeps::Room,
-    creeps: &Vec<screeps::Creep>,
-    harvesters: &Vec<screeps::Creep>,
-    haulers: &Vec<screeps::Creep>,
-    builders: &Vec<screeps::Creep>,
-    repairers: &Vec<screeps::Creep>,
-    upgraders: &Vec<screeps::Creep>,
-    gatherers: &Vec<screeps::Creep>,
-) -> Vec<Part> {
-    let sources = filters::get_my_sources();
-
-    let mut needs = vec![];
-
-    if creeps.len() < 1 {
-        needs.extend(vec![Part::Carry, Part::Work, Part::Move]);
-        return needs;
-    };
-    if creeps.len() < 2 {
-        needs.extend(vec![Part::Carry, Part::Move]);
-        return needs;
-    };
-
-    if flags::get_claim_flags().len() > 0
-        && creeps
-            .iter()
-            .filter(|&c| c.get_active_bodyparts(Part::Claim) > 0)
-            .count()
-            == 0
-        && room.energy_available() >= 700
-    {
-        needs.extend(&[
-            Part::Move,
-            Part::Claim,
-            Part::Move,
-            Part::Move,
-            Part::Move,
-            Part::Move,
-            Part::Move,
-        ]);
-        return needs;
-    }
 
-    // if harvesters
-    //     .iter()
-    //     .any(|c| c.get_active_bodyparts(screeps::Part::Work) < 5)
-    // {
-    //     needs.push(Part::Move);
-    //     needs.extend(vec![Part::Work; 5]);
-    //     return needs;
-    // }
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Role {
+    Harvester,
+    Hauler,
+    Worker,
+    Upgrader,
+    Claimer,
+    Defender,
+    Scout,
+}
 
-    let drop_harvesters_parts = creeps
-        .iter()
-        .filter(|&c| !c.has_parts_for_job(JobType::Pickup) && c.has_parts_for_job(JobType::Harvest))
-        .fold(0, |acc, cur| {
-            acc + cur.get_active_bodyparts(screeps::Part::Work)
-        });
+/// What a role's body is made of: fixed `base` parts plus up to `max_units`
+/// copies of `unit`, with enough MOVE to keep `speed`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BodySpec {
+    pub role: Role,
+    pub base: Vec<Part>,
+    pub unit: Vec<Part>,
+    pub min_units: u32,
+    pub max_units: u32,
+    pub speed: Speed,
+    /// the job `contribution_per_tick` is predicted for
+    pub job: JobType,
+}
 
-    info!("Drop harvester parts: {:?}", drop_harvesters_parts);
-    if drop_harvesters_parts < sources.len() as u32 * 5 {
-        needs.push(Part::Move);
-        needs.extend(vec![Part::Work; 5]);
-        return needs;
-    };
+impl Role {
+    /// What the role is called in creep names and memory.
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Harvester => "harvester",
+            Role::Hauler => "hauler",
+            Role::Worker => "worker",
+            Role::Upgrader => "upgrader",
+            Role::Claimer => "claimer",
+            Role::Defender => "defender",
+            Role::Scout => "scout",
+        }
+    }
 
-    let heavy_hauler_parts = creeps
+    pub fn from_name(name: &str) -> Option<Role> {
+        [
+            Role::Harvester,
+            Role::Hauler,
+            Role::Worker,
+            Role::Upgrader,
+            Role::Claimer,
+            Role::Defender,
+            Role::Scout,
+        ]
         .iter()
-        .filter(|&c| {
-            c.has_parts_for_job(JobType::Transfer)
-                && !c.has_parts_for_job(JobType::Harvest)
-                && c.ticks_to_live().unwrap_or(0) > 15
-        })
-        .fold(0, |acc, cur| {
-            acc + cur.get_active_bodyparts(Part::Carry) + cur.get_active_bodyparts(Part::Move)
-        });
-
-    if heavy_hauler_parts < sources.len() as u32 * 4 {
-        needs.extend(&[Part::Move, Part::Carry].repeat(8));
-        return needs;
+        .copied()
+        .find(|r| r.name() == name)
     }
 
-    let builder_parts = creeps
-        .iter()
-        .filter(|&c| c.has_parts_for_job(JobType::Build) && c.ticks_to_live().unwrap_or(0) > 0)
-        .fold(0, |acc, cur| {
-            acc + cur.get_active_bodyparts(Part::Carry) + cur.get_active_bodyparts(Part::Work)
-        });
+    /// The role an existing body was most likely built for.
+    pub fn of_body(body: &[Part]) -> Role {
+        let count = |part: Part| body.iter().filter(|&p| *p == part).count();
+        if count(Part::Claim) > 0 {
+            Role::Claimer
+        } else if count(Part::RangedAttack) > 0 {
+            Role::Defender
+        } else {
+            match (count(Part::Work), count(Part::Carry)) {
+                (0, 0) => Role::Scout,
+                (_, 0) => Role::Harvester,
+                (0, _) => Role::Hauler,
+                (work, carry) if carry == 1 && work > 1 => Role::Upgrader,
+                _ => Role::Worker,
+            }
+        }
+    }
 
-    if builder_parts < 1 {
-        needs.extend(&[Part::Move, Part::Carry, Part::Work].repeat(8));
-    };
+    pub fn spec(self) -> BodySpec {
+        let (base, unit, min_units, max_units, speed, job) = match self {
+            // 5 WORK empties a source right as it regenerates
+            Role::Harvester => (
+                vec![],
+                vec![Part::Work],
+                1,
+                5,
+                Speed::Crawl,
+                JobType::Harvest,
+            ),
+            Role::Hauler => (
+                vec![],
+                vec![Part::Carry],
+                1,
+                33,
+                Speed::Road,
+                JobType::Transfer,
+            ),
+            Role::Worker => (
+                vec![],
+                vec![Part::Work, Part::Carry],
+                1,
+                16,
+                Speed::Road,
+                JobType::Build,
+            ),
+            Role::Upgrader => (
+                vec![Part::Carry],
+                vec![Part::Work],
+                1,
+                15,
+                Speed::Road,
+                JobType::Upgrade,
+            ),
+            Role::Claimer => (
+                vec![],
+                vec![Part::Claim],
+                1,
+                1,
+                Speed::Plain,
+                JobType::Claim,
+            ),
+            Role::Defender => (
+                vec![],
+                vec![Part::RangedAttack],
+                1,
+                25,
+                Speed::Plain,
+                JobType::DefendR,
+            ),
+            Role::Scout => (vec![], vec![], 0, 0, Speed::Crawl, JobType::Scout),
+        };
+        BodySpec {
+            role: self,
+            base,
+            unit,
+            min_units,
+            max_units,
+            speed,
+            job,
+        }
+    }
+}
 
-    needs.extend(&[Part::Move, Part::Move, Part::Carry, Part::Work]);
-    needs
+pub fn body_cost(body: &[Part]) -> u32 {
+    body.iter().map(|p| p.cost()).sum()
 }
-pub fn oget_needs(
-    room: &screeps::Room,
-    creeps: &Vec<screeps::Creep>,
-    harvesters: &Vec<screeps::Creep>,
-    haulers: &Vec<screeps::Creep>,
-    builders: &Vec<screeps::Creep>,
-    repairers: &Vec<screeps::Creep>,
-    upgraders: &Vec<screeps::Creep>,
-    gatherers: &Vec<screeps::Creep>,
-) -> Vec<Part> {
-    let sources = filters::get_my_sources();
-    // let source_slots = sources.iter().flat_map(|s| {
-    //     s.pos().neighbors().iter()
-    //         .filter(|&pos| { pos.move_cost().is_some() })
-    //         .map(|&pos| pos)
-    //         .collect::<Vec<screeps::Position>>()
-    // }).collect::<Vec<screeps::Position>>();
-
-    let mut needs = vec![];
-
-    if creeps.len() < 3 {
-        needs.extend(vec![Part::Carry, Part::Work, Part::Move, Part::Move]);
-        return needs;
+
+impl BodySpec {
+    /// MOVE parts that keep `weight` other parts (CARRY counted loaded)
+    /// fatigue-free at this spec's speed.
+    fn moves(&self, weight: u32) -> u32 {
+        match self.speed {
+            Speed::Road => (weight + 1) / 2,
+            Speed::Plain => weight,
+            Speed::Crawl => 1,
+        }
+        .max(1)
     }
 
-    let drop_harvester_parts = creeps
-        .iter()
-        .filter(|&c| {
-            c.has_parts_for_job(JobType::Harvest)
-                && !c.has_parts_for_job(JobType::Claim)
-                && !c.has_parts_for_job(JobType::Attack)
-                && !c.has_parts_for_job(JobType::AttackR)
-                && c.ticks_to_live().unwrap_or(0) > 15
-                && c.store_capacity(Some(screeps::ResourceType::Energy)) == 0
-        })
-        .fold(0, |acc, cur| acc + cur.get_active_bodyparts(Part::Work));
+    fn build(&self, units: u32) -> Vec<Part> {
+        let mut body = self.base.to_vec();
+        for _ in 0..units {
+            body.extend(self.unit.iter());
+        }
+        let weight = body.iter().filter(|&p| *p != Part::Move).count() as u32;
+        body.extend(vec![Part::Move; self.moves(weight) as usize]);
+        body
+    }
 
-    if drop_harvester_parts < sources.len() as u32 * 5 {
-        needs.push(Part::Move);
-        needs.extend(vec![Part::Work; 5]);
-        return needs;
+    /// The biggest body within `energy` and the part limit, with its predicted
+    /// `contribution_per_tick` at `self.job`. Contribution grows with units, so
+    /// the biggest that fits is the best.
+    pub fn solve(&self, energy: u32) -> Option<(Vec<Part>, u32)> {
+        let max_units = if self.unit.is_empty() {
+            self.min_units
+        } else {
+            self.max_units
+        };
+        (self.min_units..=max_units.max(self.min_units))
+            .rev()
+            .map(|n| self.build(n))
+            .find(|b| b.len() <= MAX_PARTS && body_cost(b) <= energy)
+            .map(|b| {
+                let contribution = body_contribution(&b, self.job);
+                (b, contribution)
+            })
     }
+}
 
-    let heavy_hauler_parts = creeps
+pub fn get_hostile_needs(room: &screeps::Room, defenders: &Vec<screeps::Creep>) -> Role {
+    Role::Defender
+}
+/// Creeps whose metadata gives them `role`.
+pub fn get_by_role(creeps: &Vec<screeps::Creep>, role: &str) -> Vec<screeps::Creep> {
+    creeps
         .iter()
-        .filter(|&c| {
-            c.has_parts_for_job(JobType::Transfer)
-                && !c.has_parts_for_job(JobType::Harvest)
-                && c.ticks_to_live().unwrap_or(0) > 15
+        .filter(|c| {
+            meta::read(c)
+                .map(|m| m.role.name() == role)
+                .unwrap_or(false)
         })
-        .fold(0, |acc, cur| {
-            acc + cur.get_active_bodyparts(Part::Carry) + cur.get_active_bodyparts(Part::Move)
-        });
-    if heavy_hauler_parts < sources.len() as u32 * 4 || haulers.len() < 4 {
-        needs.extend(&[Part::Move, Part::Carry].repeat(8));
-        return needs;
-    }
-
-    needs.extend(&[Part::Move, Part::Move, Part::Carry, Part::Work]);
-
-    return needs;
+        .cloned()
+        .collect()
 }
-pub fn init(
-    creeps: Vec<screeps::Creep>,
-    harvesters: Vec<screeps::Creep>,
-    haulers: Vec<screeps::Creep>,
-    builders: Vec<screeps::Creep>,
-    repairers: Vec<screeps::Creep>,
-    upgraders: Vec<screeps::Creep>,
-    gatherers: Vec<screeps::Creep>,
-    defenders: Vec<screeps::Creep>,
-) {
+
+pub fn init(creeps: &[screeps::Creep], defenders: &[screeps::Creep]) {
     let spawns = filters::get_my_spawns();
+    let time = screeps::game::time();
 
+    // one pass per room, however many spawns it has
     let mut visited_rooms = vec![];
-    spawns
-        .iter()
-        .filter(|spawn| !spawn.is_spawning())
-        .for_each(|spawn| {
-            if !visited_rooms.contains(&spawn) {
-                visited_rooms.push(spawn);
-
-                let energy_target = match spawn.room() {
-                    Some(r) => r.energy_available(),
-                    None => 0,
-                };
-                if energy_target < 250 {
-                    return;
-                }
-
-                let mut needs = vec![];
-
-                let hostile = filters::get_hostility(&spawn.room().unwrap());
-                if hostile.0.is_empty()
-                    && hostile.1.is_empty()
-                    && hostile.2.is_empty()
-                    && hostile.3.is_empty()
-                    && hostile.4.is_empty()
-                {
-                    needs = get_needs(
-                        &spawn.room().unwrap(),
-                        &creeps,
-                        &harvesters,
-                        &haulers,
-                        &builders,
-                        &repairers,
-                        &upgraders,
-                        &gatherers,
-                    );
-                } else {
-                    needs = get_hostile_needs(&spawn.room().unwrap(), &defenders);
-                }
-
-                match spawn.construct_creep(energy_target, needs, spawn) {
-                    Some(mut tmpl) => {
-                        tmpl.reduce_cost(energy_target);
-                        tmpl.sort_body();
-                        spawn.spawn_creep_with_options(&tmpl.body, &tmpl.name, &tmpl.opts);
-                    }
-                    None => {}
-                }
+    for spawn in spawns.iter().filter(|spawn| !spawn.is_spawning()) {
+        let room = match spawn.room() {
+            Some(r) => r,
+            None => continue,
+        };
+        if visited_rooms.contains(&room.name()) {
+            continue;
+        }
+        visited_rooms.push(room.name());
+
+        let hostile = filters::get_hostility(&room);
+        if hostile.0.is_empty()
+            && hostile.1.is_empty()
+            && hostile.2.is_empty()
+            && hostile.3.is_empty()
+            && hostile.4.is_empty()
+        {
+            if recovery::active(room.name()) {
+                recovery::spawn(&room, &colony::members(room.name(), creeps));
+            } else {
+                demand::plan(&room, creeps);
             }
-        })
+        } else {
+            spawnqueue::enqueue(SpawnRequest {
+                spec: get_hostile_needs(&room, &defenders.to_vec()).spec(),
+                room: room.name().to_string(),
+                priority: JobClass::Defense,
+                deadline: time + DEFENSE_DEADLINE,
+                requester: format!("defense:{}", room.name()),
+                policy: Policy::Shrink,
+                queued: time,
+                not_before: 0,
+                predecessor: None,
+                target: None,
+            });
+        }
+    }
+}
+
+/// What `body` adds to `job_type` per tick of work.
+pub fn body_contribution(body: &[Part], job_type: JobType) -> u32 {
+    match job_type {
+        JobType::Harvest => body.iter().filter(|&p| *p == Part::Work).count() as u32 * 2,
+        JobType::Build => body.iter().filter(|&p| *p == Part::Work).count() as u32 * 5,
+        JobType::Repair => body.iter().filter(|&p| *p == Part::Work).count() as u32 * 100,
+        JobType::Upgrade => body.iter().filter(|&p| *p == Part::Work).count() as u32 * 1,
+        JobType::Transfer => body.iter().filter(|&p| *p == Part::Carry).count() as u32, // TODO: None?
+        JobType::Withdraw => body.iter().filter(|&p| *p == Part::Carry).count() as u32, // TODO: None?
+        JobType::Pickup => body.iter().filter(|&p| *p == Part::Carry).count() as u32 * 50,
+        JobType::Claim => body.iter().filter(|&p| *p == Part::Claim).count() as u32,
+        JobType::Reserve => body.iter().filter(|&p| *p == Part::Claim).count() as u32,
+        JobType::Attack => body.iter().filter(|&p| *p == Part::Attack).count() as u32 * 30,
+        JobType::AttackR => body.iter().filter(|&p| *p == Part::RangedAttack).count() as u32 * 10,
+        JobType::Defend => body.iter().filter(|&p| *p == Part::Attack).count() as u32 * 30,
+        JobType::DefendR => body.iter().filter(|&p| *p == Part::RangedAttack).count() as u32 * 10,
+        JobType::Heal => body.iter().filter(|&p| *p == Part::Heal).count() as u32 * 12,
+        JobType::Station => 1,
+        JobType::Scout => 1,
+        JobType::Dismantle => body.iter().filter(|&p| *p == Part::Work).count() as u32 * 50,
+        JobType::Renew | JobType::Recycle => 0,
+    }
 }
 
 pub trait SpawnProperties {
-    fn construct_creep(
-        self: &Self,
-        energy_target: u32,
-        needs: Vec<Part>,
-        spawn: &screeps::StructureSpawn,
-    ) -> Option<BodyTemplate>;
+    /// The best body for `spec` this spawn can pay for with `energy`, and its
+    /// predicted contribution per tick.
+    fn construct_creep(self: &Self, spec: &BodySpec, energy: u32) -> Option<(BodyTemplate, u32)>;
 }
 
 impl SpawnProperties for screeps::StructureSpawn {
-    fn construct_creep(
-        self: &Self,
-        energy_target: u32,
-        needs: Vec<Part>,
-        spawn: &screeps::StructureSpawn,
-    ) -> Option<BodyTemplate> {
-        let mut body = BodyTemplate::new(needs.to_vec(), self);
-        while body.cost < energy_target {
-            needs.iter().for_each(|p| body.add_part(*p))
-        }
-        body.reduce_cost(energy_target);
-        body.sort_body();
-        if body.body.len() >= 3 {
-            Some(body)
-        } else {
-            None
-        }
+    fn construct_creep(self: &Self, spec: &BodySpec, energy: u32) -> Option<(BodyTemplate, u32)> {
+        let (body, contribution) = spec.solve(energy)?;
+        let mut tmpl = BodyTemplate::new(body, self, spec.role);
+        tmpl.sort_body();
+        Some((tmpl, contribution))
     }
 }
 
 pub struct BodyTemplate {
-    name: String,
-    body: Vec<Part>,
+    pub name: String,
+    pub body: Vec<Part>,
     cost: u32,
-    opts: SpawnOptions,
+    pub opts: SpawnOptions,
+    /// the memory `opts` spawns the creep with
+    pub mem: MemoryReference,
     spawned_at: screeps::Position,
 }
 
 impl BodyTemplate {
-    fn new(body: Vec<Part>, spawn: &StructureSpawn) -> Self {
-        let cost = body.iter().map(|p| p.cost()).sum::<u32>() + 50;
+    fn new(body: Vec<Part>, spawn: &StructureSpawn, role: Role) -> Self {
+        let cost = body_cost(&body);
         let mem = MemoryReference::new();
 
-        let count = body.iter().count();
-
         return BodyTemplate {
-            name: String::from(format!("{}-{}-{}", "creep-", count, screeps::game::time())),
+            // unique as long as a spawn starts one creep per tick
+            name: format!("{}-{}-{}", role.name(), screeps::game::time(), spawn.name()),
             body,
             cost,
-            opts: SpawnOptions::new().memory(mem),
+            opts: SpawnOptions::new().memory(mem.clone()),
+            mem,
             spawned_at: spawn.pos(),
         };
     }
@@ -301,30 +345,9 @@ impl BodyTemplate {
     }
 
     fn recost(self: &mut Self) -> u32 {
-        self.cost = self.body.iter().map(|p| p.cost()).sum::<u32>() + 50;
+        self.cost = body_cost(&self.body);
         self.cost
     }
-
-    fn reduce_cost(self: &mut Self, parts_cost_cap: u32) {
-        while self.cost > parts_cost_cap && self.body.len() > 0 {
-            let extra = self
-                .body
-                .iter()
-                .max_by_key(|&bp| self.body.iter().filter(|&p| p == bp).count())
-                .unwrap()
-                .to_owned();
-
-            self.body
-                .sort_unstable_by_key(|bp| if *bp == extra { 1 } else { 0 });
-            self.body.pop();
-            self.recost();
-        }
-    }
-
-    fn add_part(self: &mut Self, part: Part) {
-        self.body.push(part);
-        self.recost();
-    }
 }
 
 impl JobProperties for BodyTemplate {
@@ -337,36 +360,7 @@ impl JobProperties for BodyTemplate {
     }
 
     fn contribution_per_tick(&self, job_type: JobType) -> u32 {
-        match job_type {
-            JobType::Harvest => self.body.iter().filter(|&p| *p == Part::Work).count() as u32 * 2,
-            JobType::Build => self.body.iter().filter(|&p| *p == Part::Work).count() as u32 * 5,
-            JobType::Repair => self.body.iter().filter(|&p| *p == Part::Work).count() as u32 * 100,
-            JobType::Upgrade => self.body.iter().filter(|&p| *p == Part::Work).count() as u32 * 1,
-            JobType::Transfer => self.body.iter().filter(|&p| *p == Part::Carry).count() as u32, // TODO: None?
-            JobType::Withdraw => self.body.iter().filter(|&p| *p == Part::Carry).count() as u32, // TODO: None?
-            JobType::Pickup => self.body.iter().filter(|&p| *p == Part::Carry).count() as u32 * 50,
-            JobType::Claim => self.body.iter().filter(|&p| *p == Part::Claim).count() as u32,
-            JobType::Reserve => self.body.iter().filter(|&p| *p == Part::Claim).count() as u32,
-            JobType::Attack => self.body.iter().filter(|&p| *p == Part::Attack).count() as u32 * 30,
-            JobType::AttackR => {
-                self.body
-                    .iter()
-                    .filter(|&p| *p == Part::RangedAttack)
-                    .count() as u32
-                    * 10
-            }
-            JobType::Defend => self.body.iter().filter(|&p| *p == Part::Attack).count() as u32 * 30,
-            JobType::DefendR => {
-                self.body
-                    .iter()
-                    .filter(|&p| *p == Part::RangedAttack)
-                    .count() as u32
-                    * 10
-            }
-            JobType::Heal => self.body.iter().filter(|&p| *p == Part::Heal).count() as u32 * 12,
-            JobType::Station => 1,
-            JobType::Scout => 1,
-        }
+        body_contribution(&self.body, job_type)
     }
 
     fn has_parts_for_job(&self, job_type: crate::jobs::JobType) -> bool {
@@ -387,6 +381,9 @@ impl JobProperties for BodyTemplate {
             JobType::DefendR => vec![Part::RangedAttack],
             JobType::Heal => vec![Part::Heal],
             JobType::Scout => vec![Part::Move],
+            JobType::Dismantle => vec![Part::Move, Part::Work],
+            JobType::Renew => vec![Part::Move],
+            JobType::Recycle => vec![Part::Move],
         };
 
         bp_reqs
@@ -396,7 +393,7 @@ impl JobProperties for BodyTemplate {
 
     fn job_runtime(
         &self,
-        target: &dyn screeps::HasId,
+        target: &screeps::Position,
         job_type: crate::jobs::JobType,
     ) -> (u32, u32, u32) {
         let start_ticks = (self.body.len() * 3) as u32;
@@ -432,6 +429,9 @@ impl JobProperties for BodyTemplate {
             JobType::DefendR => 1500,
             JobType::Heal => 1500,
             JobType::Scout => 1500,
+            JobType::Dismantle => 1500,
+            JobType::Renew => 1,
+            JobType::Recycle => 1,
         };
 
         let finish_ticks = (1500 - start_ticks).min(job_duration);
@@ -443,55 +443,9 @@ impl JobProperties for BodyTemplate {
         );
     }
 
-    #[inline]
+    /// Path from the spawn to `target` and the ticks a fresh copy of the body takes along it.
     fn astar(&self, target: &screeps::Position) -> SearchMove {
-        let mut body = self.count_bp_vec(vec![
-            Part::Attack,
-            Part::Claim,
-            Part::Heal,
-            Part::RangedAttack,
-            Part::Tough,
-            Part::Work,
-            Part::Carry,
-            Part::Move,
-        ]);
-
-        let body_move = body.pop().unwrap_or(0);
-        let body_carry = body.pop().unwrap_or(0);
-
-        if body_move == 0 {
-            return SearchMove {
-                arrive_ticks: u32::MAX,
-                search_results: None,
-            };
-        }
-
-        // let carry_weight = *body.get(6).unwrap_or(&0) as f32 * 50.;
-        let loaded_carry = 0;
-
-        // weight = how much gross fatigue is accumulated per move on road
-        let weight = body
-            .iter()
-            .fold(loaded_carry.min(body_carry), |acc, cur| acc + cur);
-
-        let ticks_road = (0.5 * weight as f32 / body_move as f32).ceil();
-        let ticks_plain = (1.0 * weight as f32 / body_move as f32).ceil();
-        let ticks_swamp = (5.0 * weight as f32 / body_move as f32).ceil();
-
-        let heuristic = self.spawned_at.get_range_to(target) as f32 * ticks_road;
-
-        let search_opts = screeps::pathfinder::SearchOptions::default()
-            .plain_cost(ticks_plain as u8)
-            .swamp_cost(ticks_swamp as u8)
-            .heuristic_weight(heuristic.into());
-
-        //TODO Visuals
-
-        let results = screeps::pathfinder::search(&self.spawned_at, target, 1, search_opts);
-        SearchMove {
-            arrive_ticks: results.cost,
-            search_results: Some(results),
-        }
+        Mobility::of_body(&self.body, 0).search(&self.spawned_at, target)
     }
 
     fn astar_move(&self, pos: &screeps::Position) {}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+
+use log::{info, warn};
+use screeps::{
+    game, ReturnCode, RoomName, RoomObjectProperties, SharedCreepProperties, StructureSpawn,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::jobs::JobClass;
+use crate::meta::{self, CreepMeta};
+use crate::spawning::{body_cost, BodySpec, Role, SpawnProperties};
+use crate::{colony, filters};
+
+/// Spawns further than this many rooms from a request's room never take it.
+const MAX_SPAWN_DISTANCE: u32 = 3;
+
+/// What to do when the best body for a request isn't affordable yet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Policy {
+    /// hold the spawn until its room can pay for the full-size body
+    Wait,
+    /// spawn the biggest body its room can pay for now
+    Shrink,
+}
+
+/// One creep someone wants, kept in `Memory.spawn_queue` until it spawns or
+/// its deadline passes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpawnRequest {
+    pub spec: BodySpec,
+    /// room the creep will work for
+    pub room: String,
+    pub priority: JobClass,
+    /// dropped if still queued after this tick
+    pub deadline: u32,
+    /// who asked; a new request from the same requester replaces the old one
+    pub requester: String,
+    pub policy: Policy,
+    pub queued: u32,
+    /// not served before this tick
+    #[serde(default)]
+    pub not_before: u32,
+    /// the creep this one replaces, told its successor's name on spawn
+    #[serde(default)]
+    pub predecessor: Option<String>,
+    /// room the creep is sent to, when it isn't `room`
+    #[serde(default)]
+    pub target: Option<String>,
+}
+
+pub fn load() -> Vec<SpawnRequest> {
+    match screeps::memory::root().string("spawn_queue") {
+        Ok(Some(json)) => match serde_json::from_str::<Vec<SpawnRequest>>(&json) {
+            Ok(queue) => queue,
+            Err(e) => {
+                warn!("Deserialization error: {:?}", e);
+                vec![]
+            }
+        },
+        _ => vec![],
+    }
+}
+
+fn save(queue: &[SpawnRequest]) {
+    match serde_json::to_string(queue) {
+        Ok(k) => screeps::memory::root().set("spawn_queue", k),
+        Err(e) => warn!("Serialization error: {:?}", e),
+    }
+}
+
+/// Queues `request`, replacing anything queued by the same requester.
+pub fn enqueue(request: SpawnRequest) {
+    let mut queue = load();
+    queue.retain(|r| r.requester != request.requester);
+    queue.push(request);
+    save(&queue);
+}
+
+/// Drops every request whose requester starts with `prefix`.
+pub fn cancel(prefix: &str) {
+    let mut queue = load();
+    let before = queue.len();
+    queue.retain(|r| !r.requester.starts_with(prefix));
+    if queue.len() != before {
+        save(&queue);
+    }
+}
+
+/// Whether a creep of `role` is queued for `room`.
+pub fn wanted(room: RoomName, role: Role) -> bool {
+    let room = room.to_string();
+    load().iter().any(|r| r.room == room && r.spec.role == role)
+}
+
+/// The free spawn that should take `request`: nearest to its room first, then
+/// the one that can build the most useful body. Returns the spawn's index in
+/// `free` and the energy the body may use.
+fn pick_spawn(
+    free: &[StructureSpawn],
+    request: &SpawnRequest,
+    target: RoomName,
+    spent: &HashMap<RoomName, u32>,
+) -> Option<(usize, u32)> {
+    free.iter()
+        .enumerate()
+        .filter_map(|(i, spawn)| {
+            let room = spawn.room()?;
+            let distance = colony::distance(room.name(), target);
+            if distance > MAX_SPAWN_DISTANCE {
+                return None;
+            }
+            let available = room
+                .energy_available()
+                .saturating_sub(*spent.get(&room.name()).unwrap_or(&0));
+            let budget = match request.policy {
+                Policy::Wait => room.energy_capacity_available(),
+                Policy::Shrink => available,
+            };
+            let (_, contribution) = request.spec.solve(budget)?;
+            Some((i, budget, distance, contribution))
+        })
+        .min_by_key(|&(_, _, distance, contribution)| (distance, u32::MAX - contribution))
+        .map(|(i, budget, _, _)| (i, budget))
+}
+
+/// Hands queued requests to free spawns, most urgent first.
+pub fn run() {
+    let time = screeps::game::time();
+    let mut queue = load();
+    let before = queue.len();
+    queue.retain(|r| {
+        if r.deadline < time {
+            warn!("spawn request from {} expired", r.requester);
+        }
+        r.deadline >= time
+    });
+    if queue.is_empty() {
+        if before > 0 {
+            save(&queue);
+        }
+        return;
+    }
+    queue.sort_by_key(|r| (r.priority, r.deadline, r.queued));
+
+    let mut free = filters::get_my_spawns()
+        .into_iter()
+        .filter(|s| !s.is_spawning())
+        .collect::<Vec<StructureSpawn>>();
+    // spawns in one room share its energy
+    let mut spent: HashMap<RoomName, u32> = HashMap::new();
+    let mut done = vec![];
+
+    for (idx, request) in queue.iter().enumerate() {
+        if free.is_empty() {
+            break;
+        }
+        if request.not_before > time {
+            continue;
+        }
+        let target = match RoomName::new(&request.room) {
+            Ok(r) => r,
+            Err(_) => {
+                warn!("spawn request for unknown room {}", request.room);
+                done.push(idx);
+                continue;
+            }
+        };
+        let (i, budget) = match pick_spawn(&free, request, target, &spent) {
+            Some(p) => p,
+            None => continue,
+        };
+        let spawn = free.remove(i);
+        let room = match spawn.room() {
+            Some(r) => r,
+            None => continue,
+        };
+        let available = room
+            .energy_available()
+            .saturating_sub(*spent.get(&room.name()).unwrap_or(&0));
+
+        let (tmpl, contribution) = match spawn.construct_creep(&request.spec, budget) {
+            Some(t) => t,
+            None => continue,
+        };
+        let cost = body_cost(&tmpl.body);
+        if cost > available {
+            // waiting: keep the room's energy for this request
+            spent.insert(room.name(), room.energy_available());
+            continue;
+        }
+        let predecessor = request
+            .predecessor
+            .as_ref()
+            .and_then(|o| game::creeps::get(o));
+        meta::write(
+            &tmpl.mem,
+            &CreepMeta {
+                role: request.spec.role,
+                home: target,
+                target: request.target.as_ref().and_then(|t| RoomName::new(t).ok()),
+                spec: request.spec.clone(),
+                generation: predecessor
+                    .as_ref()
+                    .and_then(|p| meta::read(p))
+                    .map(|m| m.generation + 1)
+                    .unwrap_or(0),
+                spawned: time,
+            },
+        );
+        if let Some(old) = &request.predecessor {
+            tmpl.mem.set("predecessor", old.as_str());
+        }
+        match spawn.spawn_creep_with_options(&tmpl.body, &tmpl.name, &tmpl.opts) {
+            ReturnCode::Ok => {
+                if let Some(old) = &predecessor {
+                    old.memory().set("successor", tmpl.name.as_str());
+                }
+                info!(
+                    "{} spawning {:?} for {} ({}), {} per tick",
+                    spawn.name(),
+                    request.spec.role,
+                    request.room,
+                    request.requester,
+                    contribution
+                );
+                *spent.entry(room.name()).or_insert(0) += cost;
+                done.push(idx);
+            }
+            r => warn!(
+                "{} couldn't spawn for {}: {:?}",
+                spawn.name(),
+                request.requester,
+                r
+            ),
+        }
+    }
+
+    if !done.is_empty() || queue.len() != before {
+        let queue = queue
+            .into_iter()
+            .enumerate()
+            .filter(|(idx, _)| !done.contains(idx))
+            .map(|(_, r)| r)
+            .collect::<Vec<SpawnRequest>>();
+        save(&queue);
+    }
+}
+
+/// The queue in the order it will be served; what `spawn_queue()` prints.
+pub fn report() -> String {
+    let mut queue = load();
+    if queue.is_empty() {
+        return "spawn queue is empty".to_string();
+    }
+    queue.sort_by_key(|r| (r.priority, r.deadline, r.queued));
+    let time = screeps::game::time();
+    queue
+        .iter()
+        .map(|r| {
+            format!(
+                "{:?} {:?} for {} by {} ({:?}, waited {}, starts in {}, {} ticks left)",
+                r.priority,
+                r.spec.role,
+                r.room,
+                r.requester,
+                r.policy,
+                time.saturating_sub(r.queued),
+                r.not_before.saturating_sub(time),
+                r.deadline.saturating_sub(time)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
@@ -0,0 +1,132 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use log::info;
+use screeps::{Creep, HasPosition, Position, SharedCreepProperties};
+
+/// Positions remembered per creep.
+const HISTORY: usize = 6;
+
+/// Ticks without progress before a creep routes around other creeps.
+const STUCK_TICKS: u32 = 2;
+
+/// Ticks without progress before a creep gives up on its target.
+const GIVE_UP_TICKS: u32 = 10;
+
+/// A target a creep gave up on isn't offered to it again for this long.
+const BLOCKED_TICKS: u32 = 100;
+
+/// Targets this close to the one given up on count as the same.
+const BLOCKED_RANGE: u32 = 3;
+
+#[derive(Debug, Clone)]
+pub enum Progress {
+    Moving,
+    /// hasn't left its tile for this many untired ticks
+    Stuck(u32),
+    /// stepping back and forth between two tiles
+    Oscillating,
+}
+
+#[derive(Debug, Default)]
+struct History {
+    positions: Vec<Position>,
+    still: u32,
+    tick: u32,
+}
+
+thread_local! {
+    static HISTORIES: RefCell<HashMap<String, History>> = RefCell::new(HashMap::new());
+}
+
+/// Notes where `creep` is on a tick it wants to move and says how that's going.
+/// Tired ticks don't count as stuck; a gap in the ticks starts over.
+pub fn record(creep: &Creep) -> Progress {
+    let time = screeps::game::time();
+    let pos = creep.pos();
+    HISTORIES.with(|h| {
+        let mut h = h.borrow_mut();
+        let history = h.entry(creep.name()).or_default();
+        if history.tick == time {
+            return progress(history);
+        }
+        if history.tick + 1 != time {
+            *history = History::default();
+        }
+        history.tick = time;
+        if history.positions.last() == Some(&pos) {
+            if creep.fatigue() == 0 {
+                history.still += 1;
+            }
+        } else {
+            history.still = 0;
+        }
+        history.positions.push(pos);
+        if history.positions.len() > HISTORY {
+            history.positions.remove(0);
+        }
+        progress(history)
+    })
+}
+
+fn progress(history: &History) -> Progress {
+    let p = &history.positions;
+    let n = p.len();
+    if n >= 4 && p[n - 1] == p[n - 3] && p[n - 2] == p[n - 4] && p[n - 1] != p[n - 2] {
+        return Progress::Oscillating;
+    }
+    match history.still {
+        0 => Progress::Moving,
+        s => Progress::Stuck(s),
+    }
+}
+
+/// Whether `progress` calls for a path around other creeps.
+pub fn should_repath(progress: &Progress) -> bool {
+    match progress {
+        Progress::Moving => false,
+        Progress::Stuck(s) => *s >= STUCK_TICKS,
+        Progress::Oscillating => true,
+    }
+}
+
+pub fn should_give_up(progress: &Progress) -> bool {
+    match progress {
+        Progress::Stuck(s) => *s >= GIVE_UP_TICKS,
+        _ => false,
+    }
+}
+
+/// Stops offering `target` to `creep` for a while and forgets its history.
+pub fn block(creep: &Creep, target: &Position) {
+    info!(
+        "{} is stuck on its way to {}, giving up",
+        creep.name(),
+        target
+    );
+    let mem = creep.memory();
+    mem.path_set("blocked.pos", target.packed_repr());
+    mem.path_set(
+        "blocked.until",
+        (screeps::game::time() + BLOCKED_TICKS) as i32,
+    );
+    HISTORIES.with(|h| h.borrow_mut().remove(&creep.name()));
+}
+
+/// Whether `creep` gave up on reaching `target`, or somewhere next to it, recently.
+pub fn blocked(creep: &Creep, target: &Position) -> bool {
+    let mem = creep.memory();
+    match (
+        mem.path_i32("blocked.pos").unwrap_or(None),
+        mem.path_i32("blocked.until").unwrap_or(None),
+    ) {
+        (Some(pos), Some(until)) => {
+            let pos = Position::from_packed(pos);
+            // jobs name a tile near what the creep walked to
+            pos.room_name() == target.room_name()
+                && pos.get_range_to(target) <= BLOCKED_RANGE
+                && screeps::game::time() < until as u32
+        }
+        _ => false,
+    }
+}
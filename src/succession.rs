@@ -0,0 +1,153 @@
+use log::info;
+use screeps::{find, HasPosition, Part, Position, RoomObjectProperties, SharedCreepProperties};
+
+use crate::ledger::{self, Ledger};
+use crate::spawnqueue::{self, Policy, SpawnRequest};
+use crate::travel::Mobility;
+use crate::{colony, lifecycle, meta, oracle, relogic, traffic};
+
+/// Ticks each body part adds to spawning.
+const SPAWN_TICKS_PER_PART: u32 = 3;
+
+/// Replacements are queued this long before they should start spawning, so
+/// the spawn has time to gather the energy.
+const QUEUE_LEAD: u32 = 100;
+
+/// A replacement takes over once it is this close to its predecessor's job.
+const HANDOVER_RANGE: u32 = 3;
+
+/// Ticks from queueing the spawn until a copy of `body` stands at `target`.
+fn lead_time(body: &[Part], spawn: &Position, target: &Position) -> u32 {
+    body.len() as u32 * SPAWN_TICKS_PER_PART
+        + Mobility::of_body(body, 0).ticks_over(oracle::distance(spawn, target))
+}
+
+/// Queues a copy of `creep` timed to reach its job as it dies.
+fn plan(creep: &screeps::Creep, homes: &[screeps::RoomName], queue: &[SpawnRequest]) {
+    let ttl = match creep.ticks_to_live() {
+        Ok(t) => t,
+        Err(_) => return,
+    };
+    let ctx = match relogic::read_commitment(creep) {
+        Some(c) => c,
+        None => return,
+    };
+    // renewed creeps live on; obsolete ones are replaced by whatever demand asks for
+    if lifecycle::worth_renewing(creep) || lifecycle::obsolete(creep) {
+        return;
+    }
+    let room = match colony::creep_home(creep, homes).and_then(screeps::game::rooms::get) {
+        Some(r) => r,
+        None => return,
+    };
+    let spawn = match room
+        .find(find::MY_SPAWNS)
+        .into_iter()
+        .min_by_key(|s| s.pos().get_range_to(&ctx.target()))
+    {
+        Some(s) => s.pos(),
+        None => return,
+    };
+
+    let body = creep.body().iter().map(|b| b.part).collect::<Vec<Part>>();
+    let lead = lead_time(&body, &spawn, &ctx.target());
+    if ttl > lead + QUEUE_LEAD {
+        return;
+    }
+    let requester = format!("replace:{}", creep.name());
+    if queue.iter().any(|r| r.requester == requester) {
+        return;
+    }
+
+    let creep_meta = match meta::of(creep, homes) {
+        Some(m) => m,
+        None => return,
+    };
+    let (role, spec) = (creep_meta.role, creep_meta.spec);
+
+    let time = screeps::game::time();
+    info!(
+        "{} has {} ticks left, queueing a {:?} to arrive in {}",
+        creep.name(),
+        ttl,
+        role,
+        lead
+    );
+    spawnqueue::enqueue(SpawnRequest {
+        spec,
+        room: room.name().to_string(),
+        priority: ctx.class(),
+        deadline: time + ttl,
+        requester,
+        // late already: take whatever body there is energy for
+        policy: if ttl > lead {
+            Policy::Wait
+        } else {
+            Policy::Shrink
+        },
+        queued: time,
+        not_before: time + ttl.saturating_sub(lead),
+        predecessor: Some(creep.name()),
+        target: creep_meta.target.map(|t| t.to_string()),
+    });
+}
+
+/// Gives a replacement that reached its post its predecessor's job and frees
+/// the predecessor for whatever else it can do with its last ticks.
+fn handover(creep: &screeps::Creep, old: &str, ledger: &mut Ledger) {
+    let mem = screeps::memory::root();
+    let path = format!("creeps.{}.job", old);
+    if let Ok(Some(job)) = mem.get_path::<String>(&path) {
+        creep.memory().set("job", job);
+        info!("{} takes over the job of {}", creep.name(), old);
+    }
+    mem.path_del(&path);
+    ledger.release_creep(old);
+    creep.memory().del("predecessor");
+}
+
+/// Walks a replacement to its predecessor's job, which keeps working it
+/// meanwhile, and hands the job over on arrival; at once if the predecessor
+/// has no job left to hand over.
+fn relieve(creep: &screeps::Creep, old: &str, ledger: &mut Ledger) {
+    let job = screeps::game::creeps::get(old).and_then(|o| relogic::read_commitment(&o));
+    match job {
+        Some(ctx) if creep.pos().get_range_to(&ctx.target()) > HANDOVER_RANGE => {
+            traffic::move_to(creep, &ctx.target(), HANDOVER_RANGE, ctx.class());
+        }
+        _ => handover(creep, old, ledger),
+    }
+}
+
+/// Whether `creep` is a replacement still on its way to take over a job;
+/// those aren't given jobs of their own.
+pub fn en_route(creep: &screeps::Creep) -> bool {
+    creep
+        .memory()
+        .string("predecessor")
+        .unwrap_or(None)
+        .is_some()
+}
+
+/// Queues replacements for creeps about to die and walks replacements to
+/// the jobs they take over.
+pub fn run(creeps: &[screeps::Creep]) {
+    let homes = colony::homes();
+    let queue = spawnqueue::load();
+    let mut ledger = ledger::take();
+    for creep in creeps {
+        match creep.memory().string("predecessor") {
+            Ok(Some(old)) => {
+                if !creep.spawning() {
+                    relieve(creep, &old, &mut ledger);
+                }
+            }
+            _ => {
+                if creep.memory().string("successor").unwrap_or(None).is_none() {
+                    plan(creep, &homes, &queue);
+                }
+            }
+        }
+    }
+    ledger::put(ledger);
+}
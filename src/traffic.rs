@@ -0,0 +1,260 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use log::debug;
+use screeps::{
+    look, Creep, HasPosition, Position, ReturnCode, SharedCreepProperties, StructureProperties,
+    StructureType, Terrain,
+};
+
+use crate::jobs::JobClass;
+use crate::{costmatrix, paths, stuck};
+
+/// What a creep wants from the traffic layer this tick.
+#[derive(Debug, Clone)]
+struct Intent {
+    creep: Creep,
+    /// next tile on its way; None when it's parked
+    to: Option<Position>,
+    /// a parked creep may be shoved anywhere within range of this
+    anchor: Option<(Position, u32)>,
+    priority: JobClass,
+    /// stuck for a while: may swap with creeps that would otherwise stay put
+    insist: bool,
+}
+
+thread_local! {
+    static INTENTS: RefCell<HashMap<String, Intent>> = RefCell::new(HashMap::new());
+}
+
+fn register(intent: Intent) {
+    INTENTS.with(|i| {
+        i.borrow_mut().insert(intent.creep.name(), intent);
+    })
+}
+
+/// Asks to step `creep` towards `target` until within `range`. The step is
+/// taken by `resolve` at the end of the tick, which may also move whoever
+/// stands in the way.
+///
+/// A creep that stops making progress routes around other creeps and insists
+/// on swapping with whoever blocks it; one stuck for long gives up on `target`
+/// and gets NoPath.
+pub fn move_to(creep: &Creep, target: &Position, range: u32, priority: JobClass) -> ReturnCode {
+    let pos = creep.pos();
+    if pos.get_range_to(target) <= range && pos.room_name() == target.room_name() {
+        return ReturnCode::Ok;
+    }
+    let progress = stuck::record(creep);
+    if creep.fatigue() > 0 {
+        return ReturnCode::Tired;
+    }
+    if stuck::should_give_up(&progress) {
+        stuck::block(creep, target);
+        paths::forget(creep);
+        return ReturnCode::NoPath;
+    }
+    let insist = stuck::should_repath(&progress);
+    let next = if insist {
+        debug!(
+            "{} is {:?}, repathing around creeps",
+            creep.name(),
+            progress
+        );
+        paths::forget(creep);
+        costmatrix::search(&pos, target, range, true)
+            .path()
+            .first()
+            .copied()
+    } else {
+        paths::next_step(creep, target, range)
+    };
+    match next {
+        Some(next) => {
+            register(Intent {
+                creep: creep.clone(),
+                to: Some(next),
+                anchor: None,
+                priority,
+                insist,
+            });
+            ReturnCode::Ok
+        }
+        None => ReturnCode::NoPath,
+    }
+}
+
+/// Asks to step `creep` onto the neighbouring tile `next`, for movement
+/// planned elsewhere.
+pub fn step(creep: &Creep, next: &Position, priority: JobClass) {
+    register(Intent {
+        creep: creep.clone(),
+        to: Some(*next),
+        anchor: None,
+        priority,
+        insist: false,
+    })
+}
+
+/// Marks `creep` as working within `range` of `anchor`: it won't be moved
+/// further than that to let others through.
+pub fn park(creep: &Creep, anchor: &Position, range: u32, priority: JobClass) {
+    register(Intent {
+        creep: creep.clone(),
+        to: None,
+        anchor: Some((*anchor, range)),
+        priority,
+        insist: false,
+    })
+}
+
+fn walkable(pos: &Position) -> bool {
+    let (x, y) = (pos.x(), pos.y());
+    if x == 0 || y == 0 || x >= 49 || y >= 49 {
+        return false;
+    }
+    if screeps::game::map::get_room_terrain(pos.room_name()).get(x, y) == Terrain::Wall {
+        return false;
+    }
+    pos.look_for(look::STRUCTURES)
+        .iter()
+        .all(|s| match s.structure_type() {
+            StructureType::Road | StructureType::Container | StructureType::Rampart => true,
+            _ => false,
+        })
+}
+
+fn neighbours(pos: &Position) -> Vec<Position> {
+    let mut out = vec![];
+    for dx in -1i32..=1 {
+        for dy in -1i32..=1 {
+            if dx == 0 && dy == 0 {
+                continue;
+            }
+            let (x, y) = (pos.x() as i32 + dx, pos.y() as i32 + dy);
+            if x < 0 || y < 0 || x > 49 || y > 49 {
+                continue;
+            }
+            out.push(Position::new(x as u32, y as u32, pos.room_name()));
+        }
+    }
+    out
+}
+
+/// Hands out next tick's tiles, most urgent creeps first, and issues the moves.
+///
+/// A creep walking into an occupied tile swaps with the occupant if it is idle
+/// or walking the other way, shoves it aside if it is parked, and otherwise
+/// waits.
+pub fn resolve() {
+    let intents = INTENTS.with(|i| std::mem::take(&mut *i.borrow_mut()));
+
+    let mut occupant: HashMap<Position, Creep> = HashMap::new();
+    for creep in screeps::game::creeps::values() {
+        if creep.my() && !creep.spawning() {
+            occupant.insert(creep.pos(), creep);
+        }
+    }
+
+    let mut movers = intents
+        .values()
+        .filter(|i| i.to.is_some())
+        .collect::<Vec<&Intent>>();
+    movers.sort_by_key(|i| i.priority);
+
+    let mut claimed: HashSet<Position> = HashSet::new();
+    let mut done: HashSet<String> = HashSet::new();
+    let mut moves: Vec<(Creep, Position)> = vec![];
+
+    for intent in movers {
+        let name = intent.creep.name();
+        if done.contains(&name) {
+            continue;
+        }
+        done.insert(name.clone());
+        let (from, to) = (intent.creep.pos(), intent.to.unwrap());
+
+        // a stuck creep may take the tile of one left waiting on it
+        let waiting = occupant.get(&to).map_or(false, |o| {
+            o.name() != name
+                && done.contains(&o.name())
+                && o.fatigue() == 0
+                && moves.iter().all(|(c, _)| c.name() != o.name())
+        });
+        if claimed.contains(&to) && !(intent.insist && waiting) {
+            claimed.insert(from);
+            continue;
+        }
+
+        let other = match occupant.get(&to) {
+            Some(o) if o.name() != name => o,
+            _ => {
+                claimed.insert(to);
+                moves.push((intent.creep.clone(), to));
+                continue;
+            }
+        };
+        let other_name = other.name();
+        let other_intent = intents.get(&other_name);
+
+        // the occupant is heading somewhere itself
+        if let Some(other_to) = other_intent.and_then(|i| i.to) {
+            let swap =
+                (other_to == from && !done.contains(&other_name)) || (intent.insist && waiting);
+            if swap && !claimed.contains(&from) {
+                done.insert(other_name);
+                claimed.insert(from);
+                moves.push((other.clone(), from));
+            }
+            claimed.insert(to);
+            moves.push((intent.creep.clone(), to));
+            continue;
+        }
+        if done.contains(&other_name) || other.fatigue() > 0 {
+            claimed.insert(from);
+            continue;
+        }
+
+        // idle creeps swap; parked ones step aside but stay within reach of their work
+        let anchor = other_intent.and_then(|i| i.anchor);
+        let in_range = |p: &Position| match anchor {
+            Some((a, r)) => a.get_range_to(p) <= r && a.room_name() == p.room_name(),
+            None => true,
+        };
+        let shove = || {
+            neighbours(&to).into_iter().find(|p| {
+                *p != from
+                    && in_range(p)
+                    && !claimed.contains(p)
+                    && !occupant.contains_key(p)
+                    && walkable(p)
+            })
+        };
+        let swap = Some(from).filter(|p| in_range(p) && !claimed.contains(p));
+        let dest = match anchor {
+            None => swap.or_else(shove),
+            Some(_) => shove().or(swap),
+        };
+        match dest {
+            Some(dest) => {
+                done.insert(other_name);
+                claimed.insert(to);
+                claimed.insert(dest);
+                moves.push((other.clone(), dest));
+                moves.push((intent.creep.clone(), to));
+            }
+            None => {
+                claimed.insert(from);
+            }
+        }
+    }
+
+    for (creep, to) in moves.iter() {
+        match creep.pos().get_direction_to(to) {
+            Some(dir) => {
+                creep.move_direction(dir);
+            }
+            None => debug!("{} has no direction to {}", creep.name(), to),
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+
+use screeps::{
+    HasPosition, HasStore, Part, Position, ResourceType, RoomName, RoomTerrain,
+    SharedCreepProperties, Terrain,
+};
+
+use crate::costmatrix;
+use crate::jobs::SearchMove;
+
+/// Fatigue each weighed part adds per step onto a tile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ground {
+    Road = 1,
+    Plain = 2,
+    Swamp = 10,
+}
+
+/// Resources one CARRY part holds.
+const CARRY_CAPACITY: u32 = 50;
+
+fn move_boost(boost: Option<ResourceType>) -> u32 {
+    match boost {
+        Some(ResourceType::ZynthiumOxide) => 2,
+        Some(ResourceType::ZynthiumAlkalide) => 3,
+        Some(ResourceType::CatalyzedZynthiumAlkalide) => 4,
+        _ => 1,
+    }
+}
+
+fn carry_boost(boost: Option<ResourceType>) -> u32 {
+    match boost {
+        Some(ResourceType::KeaniumHydride) => 2,
+        Some(ResourceType::KeaniumAcid) => 3,
+        Some(ResourceType::CatalyzedKeaniumAcid) => 4,
+        _ => 1,
+    }
+}
+
+/// How a body moves: the fatigue it builds per step and sheds per tick.
+/// The same model serves live creeps and bodies that aren't spawned yet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mobility {
+    /// parts that weigh: everything but MOVE and empty CARRY
+    pub weight: u32,
+    /// fatigue shed per tick, 2 per working MOVE times its boost
+    pub recovery: u32,
+    /// fatigue it has right now
+    pub fatigue: u32,
+}
+
+impl Mobility {
+    /// `parts` as (part, boost, working), carrying `load` resources. CARRY
+    /// parts fill up in body order and only weigh once they hold something.
+    fn of_parts(parts: &[(Part, Option<ResourceType>, bool)], load: u32, fatigue: u32) -> Mobility {
+        let mut load = load;
+        let (mut weight, mut recovery) = (0, 0);
+        for &(part, boost, working) in parts {
+            match part {
+                Part::Move => {
+                    if working {
+                        recovery += 2 * move_boost(boost);
+                    }
+                }
+                Part::Carry => {
+                    if working && load > 0 {
+                        weight += 1;
+                        load = load.saturating_sub(CARRY_CAPACITY * carry_boost(boost));
+                    }
+                }
+                _ => weight += 1,
+            }
+        }
+        Mobility {
+            weight,
+            recovery,
+            fatigue,
+        }
+    }
+
+    pub fn of_creep(creep: &screeps::Creep) -> Mobility {
+        let parts = creep
+            .body()
+            .iter()
+            .map(|b| (b.part, b.boost, b.hits > 0))
+            .collect::<Vec<(Part, Option<ResourceType>, bool)>>();
+        Mobility::of_parts(&parts, creep.store_used_capacity(None), creep.fatigue())
+    }
+
+    /// A fresh, unboosted copy of `body` carrying `load`.
+    pub fn of_body(body: &[Part], load: u32) -> Mobility {
+        let parts = body
+            .iter()
+            .map(|&p| (p, None, true))
+            .collect::<Vec<(Part, Option<ResourceType>, bool)>>();
+        Mobility::of_parts(&parts, load, 0)
+    }
+
+    /// Ticks per step onto `ground` once underway; u32::MAX if it can't move.
+    pub fn ticks_per(&self, ground: Ground) -> u32 {
+        match self.recovery {
+            0 => u32::MAX,
+            r => ((self.weight * ground as u32 + r - 1) / r).max(1),
+        }
+    }
+
+    /// Ticks to cover `tiles` plain tiles, for when there is no path to go by.
+    pub fn ticks_over(&self, tiles: u32) -> u32 {
+        if tiles == 0 {
+            return 0;
+        }
+        self.wait()
+            .saturating_add(tiles.saturating_mul(self.ticks_per(Ground::Plain)))
+    }
+
+    /// Ticks until the fatigue it has now is gone.
+    fn wait(&self) -> u32 {
+        match (self.fatigue, self.recovery) {
+            (0, _) => 0,
+            (_, 0) => u32::MAX,
+            (f, r) => (f + r - 1) / r,
+        }
+    }
+
+    /// Exact ticks to walk `path` from where it stands, stepping once the
+    /// fatigue of the last step is gone.
+    pub fn ticks_along(&self, path: &[Position]) -> u32 {
+        if path.is_empty() {
+            return 0;
+        }
+        if self.recovery == 0 {
+            return u32::MAX;
+        }
+        let mut terrain: HashMap<RoomName, RoomTerrain> = HashMap::new();
+        let mut ticks = 0u32;
+        let mut fatigue = self.fatigue;
+        for pos in path {
+            ticks += (fatigue + self.recovery - 1) / self.recovery + 1;
+            let ground = ground(pos, &mut terrain);
+            fatigue = (self.weight * ground as u32).saturating_sub(self.recovery);
+        }
+        ticks
+    }
+
+    /// Path from `from` to next to `to` weighted by this body's step times,
+    /// with the ticks it takes.
+    pub fn search(&self, from: &Position, to: &Position) -> SearchMove {
+        if self.recovery == 0 && !from.is_near_to(to) {
+            return SearchMove {
+                arrive_ticks: u32::MAX,
+                search_results: None,
+            };
+        }
+        let cost = |g: Ground| self.ticks_per(g).min(254) as u8;
+        let results =
+            costmatrix::search_with(from, to, 1, false, cost(Ground::Plain), cost(Ground::Swamp));
+        SearchMove {
+            arrive_ticks: self.ticks_along(&results.path()),
+            search_results: Some(results),
+        }
+    }
+}
+
+fn ground(pos: &Position, terrain: &mut HashMap<RoomName, RoomTerrain>) -> Ground {
+    if costmatrix::road(pos) {
+        return Ground::Road;
+    }
+    let room = pos.room_name();
+    let t = terrain
+        .entry(room)
+        .or_insert_with(|| screeps::game::map::get_room_terrain(room));
+    match t.get(pos.x(), pos.y()) {
+        Terrain::Swamp => Ground::Swamp,
+        _ => Ground::Plain,
+    }
+}
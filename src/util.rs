@@ -1,80 +1,200 @@
-use std::convert::TryInto;
+use std::collections::HashSet;
 
 use hungarian::minimize;
-use log::info;
-use screeps::LookResult;
+use log::{info, warn};
+use screeps::{HasId, HasPosition, HasStore, Position, RawObjectId, SharedCreepProperties};
 
+use crate::contexts::{Context, ContextMap};
 use crate::filters;
-use crate::rtb::{SinkNode, SourceNode, JobAsk, JobBid};
+use crate::jobs::{JobProperties, JobType};
+use crate::ledger;
+use crate::pricing;
+use crate::rtb::{SourceNode, JobAsk, JobBid};
+use crate::succession;
+
+/// Tiles a creep can travel for one unit of bid currency.
+const TILES_PER_PRICE: u32 = 10;
+
+/// How often the per-room sink prices are logged.
+const PRICE_REPORT_INTERVAL: u32 = 100;
+
+/// Matrix entry for pairs that can't clear; kept well away from overflow.
+const INFEASIBLE: u64 = u32::MAX as u64;
+
+/// One side of a trade a creep can take this tick.
+#[derive(Debug, Copy, Clone)]
+pub enum Order {
+    Acquire(JobAsk),
+    Deliver(JobBid),
+}
+
+impl Order {
+    pub fn target(&self) -> RawObjectId {
+        match self {
+            Order::Acquire(ask) => ask.target,
+            Order::Deliver(bid) => bid.target,
+        }
+    }
+
+    pub fn max(&self) -> u32 {
+        match self {
+            Order::Acquire(ask) => ask.max,
+            Order::Deliver(bid) => bid.max,
+        }
+    }
+}
+
+fn target_pos(id: RawObjectId) -> Option<Position> {
+    screeps::game::get_object_erased(id).map(|obj| obj.pos())
+}
+
+fn travel_cost(creep: &screeps::Creep, pos: &Position) -> i64 {
+    (creep.pos().get_range_to(pos) / TILES_PER_PRICE) as i64
+}
+
+/// Highest price any sink is paying for `resource`; what an acquired load can be sold for.
+fn clearing_price(bids: &Vec<JobBid>, resource: Option<screeps::ResourceType>) -> i64 {
+    bids.iter()
+        .filter(|b| b.resource == resource)
+        .map(|b| b.bid as i64)
+        .max()
+        .unwrap_or(0)
+}
+
+/// bid - ask - travel for `creep` taking `order`, or None if it can't take it at all.
+pub fn surplus(creep: &screeps::Creep, order: &Order, pos: &Position, bids: &Vec<JobBid>) -> Option<i64> {
+    if order.target() == creep.untyped_id() { return None; }
+
+    match order {
+        Order::Acquire(ask) => {
+            if !creep.has_parts_for_job(ask.request) { return None; }
+            if creep.store_used_capacity(None) > 0 || creep.store_free_capacity(ask.resource) <= 0 { return None; }
+            Some(clearing_price(bids, ask.resource) - ask.ask as i64 - travel_cost(creep, pos))
+        }
+        Order::Deliver(bid) => {
+            if !creep.has_parts_for_job(bid.request) { return None; }
+            match bid.resource {
+                Some(res) if creep.store_used_capacity(Some(res)) == 0 => return None,
+                _ => {}
+            }
+            Some(bid.bid as i64 - creep.ask() as i64 - travel_cost(creep, pos))
+        }
+    }
+}
 
+/// Clears the rtb market for this tick: creeps with a running context keep it,
+/// the rest are matched to asks (acquire) and bids (deliver) by surplus.
 pub fn calc() {
+    let mut cmap = ContextMap::new();
+    let mut ledger = ledger::take();
+    let mut creeps = filters::get_my_creeps();
+    // replacements walking to the job they take over aren't on the market
+    creeps.retain(|c| !succession::en_route(c));
+
+    let mut served = HashSet::new();
+
+    creeps.retain(|creep| match cmap.read(&creep.untyped_id()) {
+        Some(mut ctx) if !ctx.is_done() => {
+            served.insert(ctx.target_id());
+            ctx.run(creep);
+            if ctx.is_done() {
+                ledger.release(ctx.target_id(), &creep.name());
+                cmap.delete(&creep.untyped_id());
+            } else {
+                cmap.update(&creep.untyped_id(), &ctx);
+            }
+            false
+        }
+        Some(ctx) => {
+            ledger.release(ctx.target_id(), &creep.name());
+            true
+        }
+        _ => true,
+    });
 
     let (ask_vec, bid_vec) = load_vectors();
-    let mut matrix: Vec<u64> = vec![];
-
-    // for i in &ask_vec {
-    //     for j in &bid_vec {
-    //         matrix.push((i.ask as u64).checked_sub(j.bid as u64).unwrap_or(0) as u64);
-    //     }
-    // }
-    
-    let height = ask_vec.len();
-    let width = bid_vec.len();
-    // Rectangular matrix (height < width)
-
-    // let height = 2;
-    // let width = 3;
-    // let matrix = vec![
-    //     1, 0, 5,
-    //     2, 3, 1,
-    // ];
-
-    // let assignment = minimize(&matrix, height, width);
-    
-    // info!("{:?}", assignment);
-    // let cost: u64 = assignment.iter()
-    //     .enumerate()
-    //     .filter_map(|(i, &a)| {
-    //         a.map(|j| matrix[i*width + j])
-    //     })
-    //     .sum();
-
-    // info!("{:?}", cost)
-    // assert_eq!(&assignment, &vec![Some(1), Some(2)]);
-
-    // let cost: u64 = assignment.iter()
-    //     .enumerate()
-    //     .filter_map(|(i, &a)| {
-    //         a.map(|j| matrix[i*width + j])
-    //     })
-    //     .sum();
-
-    // assert_eq!(cost, 1);
-
-    // // Rectangular matrix (width < height)
-
-    // let height = 3;
-    // let width = 2;
-    // let matrix = vec![
-    //     5, 5,
-    //     1, 0,
-    //     2, 3,
-    // ];
-
-    // let assignment = minimize(&matrix, height, width);
-
-    // assert_eq!(&assignment, &vec![None, Some(1), Some(0)]);
-
-    // let cost: u64 = assignment.iter()
-    //     .enumerate()
-    //     .filter_map(|(i, &a)| {
-    //         a.map(|j| matrix[i*width + j])
-    //     })
-    //     .sum();
-
-    // assert_eq!(cost, 2);
+
+    let orders = ask_vec.iter().map(|a| Order::Acquire(*a))
+        .chain(bid_vec.iter().map(|b| Order::Deliver(*b)))
+        .filter_map(|o| target_pos(o.target()).map(|pos| (o, pos)))
+        .collect::<Vec<(Order, Position)>>();
+
+    let height = creeps.len();
+    let width = orders.len();
+    if height == 0 || width == 0 {
+        reprice(&bid_vec, &served);
+        ledger::put(ledger);
+        return;
+    }
+
+    // orders other creeps have already claimed in full aren't on offer
+    let surpluses = creeps.iter()
+        .flat_map(|c| {
+            let ledger = &ledger;
+            let bid_vec = &bid_vec;
+            orders.iter().map(move |(o, pos)| match ledger.remaining(&o.target(), o.max(), &c.name()) {
+                0 => None,
+                _ => surplus(c, o, pos, bid_vec),
+            })
+        })
+        .collect::<Vec<Option<i64>>>();
+
+    let best = surpluses.iter().filter_map(|s| *s).max().unwrap_or(0);
+
+    // minimize (best - surplus); trades at a loss are never made
+    let matrix = surpluses.iter()
+        .map(|s| match s {
+            Some(v) if *v > 0 => (best - v) as u64,
+            _ => INFEASIBLE,
+        })
+        .collect::<Vec<u64>>();
+
+    let assignment = minimize(&matrix, height, width);
+
+    assignment.iter()
+        .enumerate()
+        .filter_map(|(i, a)| a.map(|j| (i, j)))
+        .filter(|(i, j)| matrix[i * width + j] < INFEASIBLE)
+        .for_each(|(i, j)| {
+            let creep = &creeps[i];
+            let (order, pos) = &orders[j];
+
+            let ctx = match order {
+                Order::Acquire(ask) => Context::acquire(creep, ask, pos),
+                Order::Deliver(bid) => Context::deliver(creep, bid, pos),
+            };
+
+            match ctx {
+                Some(mut ctx) => {
+                    served.insert(order.target());
+                    let available = ledger.remaining(&order.target(), order.max(), &creep.name());
+                    ledger.reserve(order.target(), &creep.name(), ctx.contribution().min(available));
+                    info!("{:?} cleared {:?} at surplus {:?}", creep.name(), order, surpluses[i * width + j]);
+                    ctx.run(creep);
+                    if ctx.is_done() {
+                        ledger.release(order.target(), &creep.name());
+                    } else {
+                        cmap.create(&creep.untyped_id(), &ctx);
+                    }
+                }
+                None => warn!("{:?} couldn't open a context for {:?}", creep.name(), order),
+            }
+        });
+
+    reprice(&bid_vec, &served);
+    ledger::put(ledger);
+
+    if screeps::game::time() % PRICE_REPORT_INTERVAL == 0 {
+        filters::get_my_rooms().iter().for_each(|room| pricing::log_report(room));
+    }
 }
 
+/// Feeds this tick's fills back into the price of every delivery bid.
+fn reprice(bids: &Vec<JobBid>, served: &HashSet<RawObjectId>) {
+    bids.iter()
+        .filter(|bid| bid.request == JobType::Transfer && pricing::is_priced(bid.ty))
+        .for_each(|bid| pricing::observe(bid.target, bid.ty, served.contains(&bid.target)));
+}
 
 pub fn load_asks_from_memory() -> Option<Vec<JobAsk>> {
     None
@@ -93,224 +213,7 @@ pub fn load_vectors() -> (Vec<JobAsk>,Vec<JobBid>) {
         Some(bids) => bids,
         None => vec![],
     };
-    filters::get_my_rooms()
-        .iter()
-        .flat_map(|room| room.look_at_area(0, 0, 50, 50))
-        .for_each(|res| {
-            match &res.look_result {
-                LookResult::Creep(st) => {
-                    match st.sink_request() {
-                        Some(req) => bid_vec.push(req),
-                        None => {}
-                    };
-                    match st.source_request() {
-                        Some(req) => ask_vec.push(req),
-                        None => {}
-                    };
-                }
-                LookResult::Energy(st) => {
-                    match st.source_request() {
-                        Some(req) => ask_vec.push(req),
-                        None => {}
-                    };
-                }
-                LookResult::Resource(st) => {
-                    match st.source_request() {
-                        Some(req) => ask_vec.push(req),
-                        None => {}
-                    };
-                }
-                LookResult::Source(st) => {
-                    match st.source_request() {
-                        Some(req) => ask_vec.push(req),
-                        None => {}
-                    };
-                }
-                LookResult::Mineral(st) => {
-                    match st.source_request() {
-                        Some(req) => ask_vec.push(req),
-                        None => {}
-                    };
-                }
-                LookResult::Deposit(st) => {
-                    match st.source_request() {
-                        Some(req) => ask_vec.push(req),
-                        None => {}
-                    };
-                }
-                LookResult::ConstructionSite(st) => {
-                    match st.sink_request() {
-                        Some(req) => bid_vec.push(req),
-                        None => {}
-                    };
-                }
-                LookResult::Tombstone(st) => {
-                    match st.source_request() {
-                        Some(req) => ask_vec.push(req),
-                        None => {}
-                    };
-                }
-                // LookResult::PowerCreep(st) => {
-                //     match st.sink_request() {
-                //         Some(req) => {bid_vec.push(req)},
-                //         None => {},
-                //     };
-                //     match st.source_request() {
-                //         Some(req) => {ask_vec.push(req)},
-                //         None => {},
-                //     };
-                // },
-                LookResult::Structure(structure) => match structure {
-                    screeps::Structure::Container(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Controller(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Extension(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Extractor(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Factory(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Lab(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Link(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Nuker(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Observer(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::PowerSpawn(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Rampart(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Road(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Spawn(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Storage(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Terminal(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Tower(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                        match st.source_request() {
-                            Some(req) => ask_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    screeps::Structure::Wall(st) => {
-                        match st.sink_request() {
-                            Some(req) => bid_vec.push(req),
-                            None => {}
-                        };
-                    }
-                    _ => {}
-                },
-                _ => {}
-            }
-        });
+    ask_vec.extend(filters::get_sources());
+    bid_vec.extend(filters::get_sinks());
     (ask_vec, bid_vec)
 }
@@ -1,11 +1,14 @@
 use log::warn;
-use screeps::{HasId, OwnedStructureProperties, Room, RoomObjectProperties, RoomPosition};
+use screeps::{
+    HasId, OwnedStructureProperties, Part, Room, RoomName, RoomObjectProperties, RoomPosition,
+    StructureProperties, StructureType,
+};
 use serde::{Deserialize, Serialize};
 
 use crate::filters;
 use crate::jobs::{JobProperties, JobType};
 
-// TODO: distance oracles that use get_time to solve new pair vertices when we have time available
+// distance oracle: see oracle.rs
 
 // #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 // pub struct ScoutingReport {
@@ -147,54 +150,135 @@ use crate::jobs::{JobProperties, JobType};
 //     };
 // }
 
-// #[derive(Debug, Serialize, Deserialize)]
-// pub enum RoomDescription {
-//     Vacant = 0,
-//     My = 1,
-//     MyReserved = 2,
-//     Hostile = 3,
-//     HostileReserved = 4,
-//     Highway = 5,
-//     SourceKeeper = 6,
-//     Center = 7,
-// }
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RoomDescription {
+    Vacant = 0,
+    My = 1,
+    MyReserved = 2,
+    Hostile = 3,
+    HostileReserved = 4,
+    Highway = 5,
+    SourceKeeper = 6,
+    Center = 7,
+}
 
-// pub trait RoomCustomActions {
-//     fn count_baddies_here(self: &Self) -> u32;
-//     fn room_type(self: &Self) -> RoomDescription;
-// }
+pub trait RoomCustomActions {
+    fn count_baddies_here(self: &Self) -> u32;
+    fn room_type(self: &Self) -> RoomDescription;
+}
 
-// impl RoomCustomActions for screeps::Room {
-//     fn count_baddies_here(self: &Self) -> u32 {
-//         let hostiles = filters::get_hostility(self);
-//         (hostiles.0.len() + hostiles.1.len()) as u32
-//     }
+impl RoomCustomActions for screeps::Room {
+    fn count_baddies_here(self: &Self) -> u32 {
+        let hostiles = filters::get_hostility(self);
+        (hostiles.0.len() + hostiles.1.len()) as u32
+    }
+
+    fn room_type(self: &Self) -> RoomDescription {
+        let my_username = my_username().unwrap_or_default();
+
+        match self.controller() {
+            Some(c) => match c.reservation() {
+                Some(r) => match r.username == my_username {
+                    true => RoomDescription::MyReserved,
+                    false => RoomDescription::HostileReserved,
+                },
+                None => match c.as_owned_structure().owner_name() {
+                    Some(n) => match n == my_username {
+                        true => RoomDescription::My,
+                        false => RoomDescription::Hostile,
+                    },
+                    None => RoomDescription::Vacant,
+                },
+            },
+            None => describe_name(self.name()).unwrap_or(RoomDescription::Highway),
+        }
+    }
+}
+
+/// Our name, read off any spawn.
+pub fn my_username() -> Option<String> {
+    screeps::game::spawns::values()
+        .first()
+        .and_then(|s| s.owner_name())
+}
+
+/// What a room without a controller is, from where it sits in its sector:
+/// highways on the lines, keepers around the middle, the middle itself.
+/// None for rooms that have a controller.
+pub fn describe_name(room: RoomName) -> Option<RoomDescription> {
+    let name = room.to_string();
+    let split = name.find(|c| c == 'N' || c == 'S')?;
+    let x = name.get(1..split)?.parse::<u32>().ok()? % 10;
+    let y = name.get(split + 1..)?.parse::<u32>().ok()? % 10;
+    match (x, y) {
+        (0, _) | (_, 0) => Some(RoomDescription::Highway),
+        (5, 5) => Some(RoomDescription::Center),
+        (4..=6, 4..=6) => Some(RoomDescription::SourceKeeper),
+        _ => None,
+    }
+}
+
+/// Rooms are surveyed this often while visible.
+const INTEL_INTERVAL: u32 = 10;
+
+/// What we last saw of a room, kept in `Memory.intel.<room>` so rooms out of
+/// sight still inform route planning.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomIntel {
+    pub kind: RoomDescription,
+    /// hostile creeps able to attack
+    pub hostiles: u32,
+    pub towers: u32,
+    pub tick: u32,
+}
+
+pub fn intel(room: RoomName) -> Option<RoomIntel> {
+    match screeps::memory::root().get_path::<String>(&format!("intel.{}", room)) {
+        Ok(Some(json)) => serde_json::from_str::<RoomIntel>(&json).ok(),
+        _ => None,
+    }
+}
+
+/// Records intel for every visible room. Rooms whose kind changed return so
+/// cached routes through them can be dropped.
+pub fn survey() -> Vec<RoomName> {
+    let time = screeps::game::time();
+    if time % INTEL_INTERVAL != 0 {
+        return vec![];
+    }
+    let mem = screeps::memory::root();
+    let mut changed = vec![];
+    for room in screeps::game::rooms::values() {
+        let hostiles = room
+            .find(screeps::find::HOSTILE_CREEPS)
+            .iter()
+            .filter(|c| {
+                c.get_active_bodyparts(Part::Attack) > 0
+                    || c.get_active_bodyparts(Part::RangedAttack) > 0
+            })
+            .count() as u32;
+        let towers = room
+            .find(screeps::find::HOSTILE_STRUCTURES)
+            .iter()
+            .filter(|s| s.structure_type() == StructureType::Tower)
+            .count() as u32;
+        let report = RoomIntel {
+            kind: room.room_type(),
+            hostiles,
+            towers,
+            tick: time,
+        };
+        if intel(room.name()).map(|i| i.kind) != Some(report.kind) {
+            changed.push(room.name());
+        }
+        match serde_json::to_string(&report) {
+            Ok(k) => mem.path_set(&format!("intel.{}", room.name()), k),
+            Err(e) => warn!("Serialization error: {:?}", e),
+        }
+    }
+    changed
+}
 
-//     fn room_type(self: &Self) -> RoomDescription {
-//         let my_username = String::from("test");
-
-//         match self.controller() {
-//             Some(c) => match c.reservation() {
-//                 Some(r) => match r.username == my_username {
-//                     true => RoomDescription::MyReserved,
-//                     false => RoomDescription::HostileReserved,
-//                 },
-//                 None => match c.as_owned_structure().owner_name() {
-//                     Some(n) => match n == my_username {
-//                         true => RoomDescription::My,
-//                         false => RoomDescription::Hostile,
-//                     },
-//                     None => RoomDescription::Vacant,
-//                 },
-//             },
-//             None => match self.energy_capacity_available() {
-//                 0 => RoomDescription::Highway,
-//                 1201..=1600 => RoomDescription::Center,
-//                 1601..=u32::MAX => RoomDescription::SourceKeeper,
-//                 _ => RoomDescription::SourceKeeper
-//             },
-//         }
-//     }
 pub struct World {
     size: u32,
 }